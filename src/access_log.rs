@@ -0,0 +1,119 @@
+// One log line per served request, written to stdout.
+use std::fmt::Write as _;                       // For building log lines in a String
+use std::io::{self, Write};                     // For writing to stdout
+use std::net::IpAddr;                           // For the client's address
+use std::time::{Duration, SystemTime, UNIX_EPOCH}; // For timestamps and response times
+
+use crate::http_date;
+
+/// How access log lines are formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable text:
+    /// `2024-05-01T12:00:00.000Z 127.0.0.1 "GET /index.html" 200 1.234ms 3f9c0a2e5b7d4e61-42`
+    #[default]
+    Plain,
+    /// One JSON object per line, with the fields `timestamp`, `client`, `method`,
+    /// `path`, `status`, `duration_ms` and `request_id`.
+    Json,
+    /// Don't log requests.
+    Off,
+}
+
+/// What is logged about one request.
+pub(crate) struct Entry<'a> {
+    pub(crate) time: SystemTime,       // When the request started arriving
+    pub(crate) client: Option<IpAddr>, // The peer's address, if the socket still knows it
+    pub(crate) method: &'a str,        // "-" if the request couldn't be parsed
+    pub(crate) path: &'a str,          // "-" if the request couldn't be parsed
+    pub(crate) status: u16,
+    pub(crate) duration: Duration,     // From the first byte of the request to the last byte of the response
+    pub(crate) request_id: &'a str,    // Sent back to the client in X-Request-Id
+}
+
+impl Entry<'_> {
+    /// Write the entry to stdout as a single line in the given format.
+    ///
+    /// The line is built up front and written under the stdout lock,
+    /// so lines from concurrent workers never interleave.
+    pub(crate) fn log(&self, format: LogFormat) {
+        let line = match format {
+            LogFormat::Plain => self.plain(),
+            LogFormat::Json => self.json(),
+            LogFormat::Off => return,
+        };
+
+        // Losing a log line isn't worth failing the request over.
+        let _ = io::stdout().lock().write_all(line.as_bytes());
+    }
+
+    fn plain(&self) -> String {
+        format!(
+            "{} {} \"{} {}\" {} {:.3}ms {}\n",
+            utc_timestamp(self.time),
+            self.client_str(),
+            self.method,
+            self.path,
+            self.status,
+            millis(self.duration),
+            self.request_id,
+        )
+    }
+
+    fn json(&self) -> String {
+        format!(
+            "{{\"timestamp\":\"{}\",\"client\":\"{}\",\"method\":\"{}\",\"path\":\"{}\",\"status\":{},\"duration_ms\":{:.3},\"request_id\":\"{}\"}}\n",
+            utc_timestamp(self.time),
+            self.client_str(),
+            json_escape(self.method),
+            json_escape(self.path),
+            self.status,
+            millis(self.duration),
+            json_escape(self.request_id),
+        )
+    }
+
+    fn client_str(&self) -> String {
+        self.client.map_or_else(|| "-".to_owned(), |ip| ip.to_string())
+    }
+}
+
+/// A duration in fractional milliseconds.
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Format `time` as an RFC 3339 UTC timestamp with millisecond precision,
+/// e.g. `2024-05-01T12:00:00.000Z`.
+fn utc_timestamp(time: SystemTime) -> String {
+    // A clock set before 1970 is logged as the epoch rather than failing.
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    let (year, month, day) = http_date::civil_from_days(days as i64);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+/// Escape `s` for use inside a JSON string literal.
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,44 @@
+// Standard base64 with padding (RFC 4648), for WebSocket handshakes and Basic credentials.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `data`, padding the last group with `=`.
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode `input`, with or without its `=` padding.
+///
+/// Returns `None` for characters outside the alphabet, padding anywhere but the end,
+/// or a length no encoding could have.
+pub(crate) fn decode(input: &str) -> Option<Vec<u8>> {
+    let digits = input.trim_end_matches('=').as_bytes();
+    let padding = input.len() - digits.len();
+    if padding > 2 || (padding > 0 && !input.len().is_multiple_of(4)) || digits.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let mut bits = 0;
+        for (i, &digit) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|&c| c == digit)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        // Two digits make one byte, three make two, four make three.
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(decoded)
+}
@@ -0,0 +1,131 @@
+// HTTP Basic authentication: a username and password sent with every request.
+use std::fmt; // For describing the middleware without its credentials
+
+use crate::base64;
+use crate::middleware::Middleware;
+use crate::request::Request;
+use crate::response::Response;
+
+/// Middleware that only lets requests through with a valid `Authorization: Basic` header.
+///
+/// Anything else gets `401 Unauthorized` with `WWW-Authenticate: Basic realm="..."`, which makes
+/// browsers ask the user to log in. By default every request is checked; [`BasicAuth::paths`]
+/// narrows that down to some paths. Basic credentials are only base64-encoded, not encrypted,
+/// so serve protected paths over TLS.
+///
+/// Built with chainable methods, e.g.
+/// `BasicAuth::new("Admin area", "admin", "s3cret").paths(["/admin"])`.
+pub struct BasicAuth {
+    realm: String,              // Shown by browsers when asking for credentials
+    credentials: Credentials,   // What counts as a valid username and password
+    paths: Option<Vec<String>>, // Path prefixes that need credentials (None = every path)
+}
+
+/// A check of a username and password, given in that order.
+type Verifier = Box<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// What a [`BasicAuth`] accepts.
+enum Credentials {
+    Fixed { username: String, password: String },
+    Verify(Verifier),
+}
+
+impl BasicAuth {
+    /// Accept exactly one `username` and `password`, compared in constant time.
+    pub fn new(realm: &str, username: &str, password: &str) -> BasicAuth {
+        BasicAuth::with_credentials(realm, Credentials::Fixed {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        })
+    }
+
+    /// Accept whatever username and password `verify` returns `true` for, such as a lookup
+    /// in a user table. Comparing passwords in constant time is up to `verify`.
+    pub fn verify<F>(realm: &str, verify: F) -> BasicAuth
+    where F: Fn(&str, &str) -> bool + Send + Sync + 'static, {
+        BasicAuth::with_credentials(realm, Credentials::Verify(Box::new(verify)))
+    }
+
+    fn with_credentials(realm: &str, credentials: Credentials) -> BasicAuth {
+        BasicAuth { realm: realm.to_owned(), credentials, paths: None }
+    }
+
+    /// Only ask for credentials on `paths` and everything below them: `/admin` covers `/admin`
+    /// and `/admin/users`, but not `/administrator`. Other requests pass through unchecked.
+    pub fn paths<I, S>(mut self, paths: I) -> BasicAuth
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.paths = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Whether a request for `path` needs credentials.
+    fn covers(&self, path: &str) -> bool {
+        self.paths.as_ref().is_none_or(|prefixes| {
+            prefixes.iter().any(|prefix| {
+                let prefix = prefix.trim_end_matches('/');
+                path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+        })
+    }
+
+    /// Whether `req` carries credentials this middleware accepts.
+    fn authorized(&self, req: &Request) -> bool {
+        let Some((username, password)) = req.header("Authorization").and_then(basic_credentials) else {
+            return false;
+        };
+        match &self.credentials {
+            // Both halves are always compared, so the time taken doesn't say which one was wrong.
+            Credentials::Fixed { username: expected_user, password: expected_password } => {
+                constant_time_eq(username.as_bytes(), expected_user.as_bytes())
+                    & constant_time_eq(password.as_bytes(), expected_password.as_bytes())
+            }
+            Credentials::Verify(verify) => verify(&username, &password),
+        }
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn call(&self, req: Request, next: &dyn Fn(Request) -> Response) -> Response {
+        if !self.covers(&req.path) || self.authorized(&req) {
+            return next(req);
+        }
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        Response::with_status(401)
+            .header("WWW-Authenticate", &format!("Basic realm=\"{realm}\", charset=\"UTF-8\""))
+    }
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("realm", &self.realm)
+            .field("paths", &self.paths)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The username and password in an `Authorization` value such as `Basic YWxhZGRpbjpvcGVuc2VzYW1l`.
+///
+/// The scheme is matched ignoring case. The password may contain colons; the username can't.
+fn basic_credentials(value: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64::decode(encoded.trim())?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_owned(), password.to_owned()))
+}
+
+/// Compare `a` and `b` in time that depends only on their lengths, not on where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let longest = a.len().max(b.len());
+    let mut diff = u8::from(a.len() != b.len());
+    for i in 0..longest {
+        diff |= a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0);
+    }
+    diff == 0
+}
@@ -0,0 +1,71 @@
+// Attributes for cookies set with `Response::set_cookie`.
+use std::fmt::Write as _; // For building Set-Cookie values in a String
+use std::time::Duration;  // For cookie lifetimes
+
+/// The attributes sent along with a cookie; see [`Response::set_cookie`](crate::Response::set_cookie).
+///
+/// The default is a session cookie with no attributes, e.g.
+/// `CookieOptions { path: Some("/".into()), http_only: true, ..CookieOptions::default() }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieOptions {
+    /// Only send the cookie with requests under this path (`Path`).
+    pub path: Option<String>,
+    /// Hide the cookie from scripts on the page (`HttpOnly`).
+    pub http_only: bool,
+    /// Only send the cookie over HTTPS (`Secure`).
+    pub secure: bool,
+    /// Keep the cookie for this long, in whole seconds (`Max-Age`). `None` for a session cookie,
+    /// and zero to delete the cookie.
+    pub max_age: Option<Duration>,
+    /// Whether the cookie is sent with requests from other sites (`SameSite`).
+    pub same_site: Option<SameSite>,
+}
+
+/// The `SameSite` cookie attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only send the cookie with requests from this site.
+    Strict,
+    /// Also send it when following a link here from another site.
+    Lax,
+    /// Send it with every request. Browsers only accept this with `secure` set.
+    None,
+}
+
+/// The value of a `Set-Cookie` header, e.g. `id=42; Path=/; HttpOnly; SameSite=Lax`.
+///
+/// # Panics
+/// Panics if `name` is empty or isn't a valid token, or `value` contains whitespace,
+/// `"`, `,`, `;`, `\` or control characters.
+pub(crate) fn set_cookie_value(name: &str, value: &str, options: &CookieOptions) -> String {
+    assert!(
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)),
+        "invalid cookie name {name:?}"
+    );
+    assert!(
+        value.chars().all(|c| c.is_ascii_graphic() && !"\",;\\".contains(c)),
+        "invalid cookie value {value:?}"
+    );
+
+    let mut header = format!("{name}={value}");
+    if let Some(path) = &options.path {
+        let _ = write!(header, "; Path={path}");
+    }
+    if let Some(max_age) = options.max_age {
+        let _ = write!(header, "; Max-Age={}", max_age.as_secs());
+    }
+    if options.http_only {
+        header.push_str("; HttpOnly");
+    }
+    if options.secure {
+        header.push_str("; Secure");
+    }
+    if let Some(same_site) = options.same_site {
+        header.push_str(match same_site {
+            SameSite::Strict => "; SameSite=Strict",
+            SameSite::Lax => "; SameSite=Lax",
+            SameSite::None => "; SameSite=None",
+        });
+    }
+    header
+}
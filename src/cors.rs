@@ -0,0 +1,134 @@
+// Cross-origin resource sharing: letting pages on other origins call the server.
+use std::time::Duration; // For how long preflight answers may be cached
+
+use crate::middleware::Middleware;
+use crate::request::{Method, Request};
+use crate::response::Response;
+
+/// Middleware that lets browsers make cross-origin requests from the allowed origins.
+///
+/// A preflight request (an `OPTIONS` request carrying `Origin` and
+/// `Access-Control-Request-Method`) from an allowed origin is answered straight away with
+/// `204 No Content` and the `Access-Control-Allow-*` headers. Other requests from an allowed
+/// origin are passed on, and the response gets `Access-Control-Allow-Origin`.
+/// Requests without an `Origin`, or from an origin that isn't allowed, pass through untouched,
+/// and the browser keeps the response from the page.
+///
+/// Built with chainable methods, e.g.
+/// `Cors::allow_origins(["https://app.example.com"]).allow_headers(["Content-Type"])`.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Origins,          // Which origins may call the server
+    methods: Vec<Method>,      // Methods allowed in cross-origin requests
+    headers: Vec<String>,      // Request headers allowed in cross-origin requests
+    max_age: Option<Duration>, // How long browsers may cache a preflight answer (None = browser default)
+}
+
+/// The origins a [`Cors`] allows.
+#[derive(Debug, Clone)]
+enum Origins {
+    Any,
+    List(Vec<String>), // Exact origins, e.g. "https://app.example.com"
+}
+
+impl Cors {
+    /// Allow requests from any origin, answering with `Access-Control-Allow-Origin: *`.
+    pub fn any_origin() -> Cors {
+        Cors::new(Origins::Any)
+    }
+
+    /// Allow requests only from `origins`, each written as the browser sends it in the `Origin` header,
+    /// e.g. `https://app.example.com` or `http://localhost:3000`.
+    ///
+    /// The allowed origin is echoed back in `Access-Control-Allow-Origin`. Every response gets
+    /// `Vary: Origin` so caches don't hand one origin's answer to another.
+    pub fn allow_origins<I, S>(origins: I) -> Cors
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Cors::new(Origins::List(origins.into_iter().map(Into::into).collect()))
+    }
+
+    fn new(origins: Origins) -> Cors {
+        Cors {
+            origins,
+            methods: vec![Method::Get, Method::Head, Method::Post],
+            headers: Vec::new(),
+            max_age: None,
+        }
+    }
+
+    /// The methods cross-origin requests may use. Defaults to `GET`, `HEAD` and `POST`.
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Cors {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// The request headers cross-origin requests may set, e.g. `Content-Type` or `Authorization`.
+    /// Defaults to none beyond the ones browsers always allow.
+    pub fn allow_headers<I, S>(mut self, headers: I) -> Cors
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.headers = headers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Let browsers cache preflight answers for `max_age`, sent in whole seconds.
+    pub fn max_age(mut self, max_age: Duration) -> Cors {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Whether requests from `origin` are allowed.
+    fn allows(&self, origin: &str) -> bool {
+        match &self.origins {
+            Origins::Any => true,
+            Origins::List(origins) => origins.iter().any(|allowed| allowed == origin),
+        }
+    }
+
+    /// Add `Access-Control-Allow-Origin` for `origin`, which must be allowed.
+    fn allow_origin(&self, response: Response, origin: &str) -> Response {
+        match self.origins {
+            Origins::Any => response.set_header("Access-Control-Allow-Origin", "*"),
+            Origins::List(_) => response.set_header("Access-Control-Allow-Origin", origin),
+        }
+    }
+
+    /// The answer to a preflight request from `origin`.
+    fn preflight(&self, origin: &str) -> Response {
+        let methods: Vec<_> = self.methods.iter().map(Method::as_str).collect();
+        let mut response = self
+            .allow_origin(Response::with_status(204), origin)
+            .header("Access-Control-Allow-Methods", &methods.join(", "));
+        if !self.headers.is_empty() {
+            response = response.header("Access-Control-Allow-Headers", &self.headers.join(", "));
+        }
+        if let Some(max_age) = self.max_age {
+            response = response.header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+        }
+        response
+    }
+}
+
+impl Middleware for Cors {
+    fn call(&self, req: Request, next: &dyn Fn(Request) -> Response) -> Response {
+        let origin = req.header("Origin").filter(|origin| self.allows(origin)).map(str::to_owned);
+        let response = match origin {
+            Some(origin) if req.method == Method::Options && req.header("Access-Control-Request-Method").is_some() => {
+                self.preflight(&origin)
+            }
+            Some(origin) => self.allow_origin(next(req), &origin),
+            None => next(req),
+        };
+
+        // With an allowlist the answer depends on the origin, whether or not this one was allowed.
+        match self.origins {
+            Origins::Any => response,
+            Origins::List(_) => response.vary("Origin"),
+        }
+    }
+}
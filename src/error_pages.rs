@@ -0,0 +1,57 @@
+// Bodies for error responses that handlers leave empty, such as 404 and 500.
+use std::fs;            // For reading page files
+use std::path::PathBuf; // For page file locations
+
+use crate::mime::content_type_for;
+use crate::response::Response;
+
+/// The built-in 404 page.
+const NOT_FOUND_PAGE: &str = "<!DOCTYPE html>\n<html lang=\"en\">\n  <head>\n    <meta charset=\"utf-8\">\n    <title>Not Found</title>\n  </head>\n  <body>\n    <h1>Not Found</h1>\n    <p>Sorry, I don't know what you're asking for.</p>\n  </body>\n</html>\n";
+
+/// The built-in 500 page.
+const INTERNAL_ERROR_PAGE: &str = "<!DOCTYPE html>\n<html lang=\"en\">\n  <head>\n    <meta charset=\"utf-8\">\n    <title>Internal Server Error</title>\n  </head>\n  <body>\n    <h1>Internal Server Error</h1>\n    <p>Something went wrong on our end.</p>\n  </body>\n</html>\n";
+
+/// The body to send with an error status; see [`Router::error_page`](crate::Router::error_page).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorPage {
+    /// Read this file each time the page is needed, with a `Content-Type` matching its extension.
+    File(PathBuf),
+    /// Send these bytes as HTML.
+    Html(Vec<u8>),
+}
+
+impl ErrorPage {
+    /// Fill in `response`'s body and `Content-Type` with this page.
+    ///
+    /// A file that can't be read is logged and replaced by the built-in page for the status, if any.
+    pub(crate) fn apply(&self, response: Response) -> Response {
+        match self {
+            ErrorPage::File(path) => match fs::read(path) {
+                Ok(body) => {
+                    let content_type = content_type_for(&path.to_string_lossy());
+                    response.body(body).set_header("Content-Type", content_type)
+                }
+                Err(e) => {
+                    log::warn!("Failed to read error page {}: {e}", path.display());
+                    apply_builtin(response)
+                }
+            },
+            ErrorPage::Html(body) => html(response, body.clone()),
+        }
+    }
+}
+
+/// Fill in `response`'s body with the built-in page for its status.
+///
+/// Only 404 and 500 have built-in pages; other statuses are left unchanged.
+pub(crate) fn apply_builtin(response: Response) -> Response {
+    match response.status {
+        404 => html(response, NOT_FOUND_PAGE),
+        500 => html(response, INTERNAL_ERROR_PAGE),
+        _ => response,
+    }
+}
+
+fn html(response: Response, body: impl Into<Vec<u8>>) -> Response {
+    response.body(body).set_header("Content-Type", "text/html; charset=utf-8")
+}
@@ -0,0 +1,31 @@
+// What answers a request once the router has picked a route for it.
+use std::sync::Arc; // For handlers shared with other routes or threads
+
+use crate::request::Request;
+use crate::response::Response;
+
+/// Something that turns a request into a response; see [`Router::add_handler`](crate::Router::add_handler).
+///
+/// Closures and functions taking `&Request` and returning a [`Response`] are handlers already.
+/// Implement it on a struct for a handler with state of its own, such as a connection pool or
+/// a cache: the same value answers every request to its route, from every worker at once, so
+/// anything it changes needs to be behind atomics or a lock.
+///
+/// An `Arc` of a handler is a handler too.
+pub trait Handler: Send + Sync {
+    /// Answer `req`.
+    fn handle(&self, req: &Request) -> Response;
+}
+
+impl<F> Handler for F
+where F: Fn(&Request) -> Response + Send + Sync, {
+    fn handle(&self, req: &Request) -> Response {
+        self(req)
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Arc<H> {
+    fn handle(&self, req: &Request) -> Response {
+        (**self).handle(req)
+    }
+}
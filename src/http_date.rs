@@ -0,0 +1,102 @@
+// Dates in the formats HTTP headers use, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+use std::time::{Duration, SystemTime, UNIX_EPOCH}; // For converting to and from seconds since the epoch
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"]; // 1970-01-01 was a Thursday
+const MONTH_NAMES: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Format `time` as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, dropping fractions of a second.
+///
+/// Times before 1970 are formatted as the epoch.
+pub(crate) fn format(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!(
+        "{}, {day:02} {} {year:04} {:02}:{:02}:{:02} GMT",
+        DAY_NAMES[(days % 7) as usize],
+        MONTH_NAMES[month as usize - 1],
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+    )
+}
+
+/// Parse an HTTP date in any of the three formats clients may send:
+/// IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`), the obsolete RFC 850 form
+/// (`Sunday, 06-Nov-94 08:49:37 GMT`) and asctime (`Sun Nov  6 08:49:37 1994`).
+///
+/// The day name isn't checked against the date. Returns `None` for anything else,
+/// including dates before 1970 or after 9999, which no real clock sends.
+pub(crate) fn parse(value: &str) -> Option<SystemTime> {
+    let value = value.trim();
+    let (day, month, year, time) = if let Some((_, rest)) = value.split_once(", ") {
+        let rest = rest.strip_suffix(" GMT")?;
+        if rest.contains('-') {
+            // RFC 850: "06-Nov-94 08:49:37"; two-digit years from 70 on are 19xx.
+            let (date, time) = rest.split_once(' ')?;
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            let year: i64 = year.parse().ok()?;
+            let year = if year < 70 { 2000 + year } else { 1900 + year };
+            (day.parse().ok()?, month, year, time)
+        } else {
+            // IMF-fixdate: "06 Nov 1994 08:49:37".
+            let mut parts = rest.split(' ');
+            let (day, month, year, time) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+            if parts.next().is_some() {
+                return None;
+            }
+            (day.parse().ok()?, month, year.parse().ok()?, time)
+        }
+    } else {
+        // asctime: "Sun Nov  6 08:49:37 1994", with the day padded by a space.
+        let mut parts = value.split_whitespace().skip(1);
+        let (month, day, time, year) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() {
+            return None;
+        }
+        (day.parse().ok()?, month, year.parse().ok()?, time)
+    };
+
+    let month = MONTH_NAMES.iter().position(|name| *name == month)? as u32 + 1;
+    let mut hms = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+        (hms.next(), hms.next(), hms.next(), hms.next())
+    else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 || !(1970..=9999).contains(&year) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day) as u64;
+    let secs = days * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) civil date
+/// (Howard Hinnant's days_from_civil, inverted).
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);                                 // Day of era, [0, 146096]
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365; // Year of era, [0, 399]
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);               // Day of year, March-based
+    let mp = (5 * doy + 2) / 153;                                    // Month, March = 0
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+/// Convert a civil date to days since 1970-01-01 (Howard Hinnant's days_from_civil).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);                                    // Year of era, [0, 399]
+    let mp = i64::from((month + 9) % 12);                              // Month, March = 0
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;                 // Day of year, March-based
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;                   // Day of era, [0, 146096]
+    era * 146_097 + doe - 719_468
+}
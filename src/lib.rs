@@ -1,14 +1,80 @@
 // Import synchronization primitives and threading utilities from the standard library
-use std::sync::{Arc, Mutex, mpsc}; // Arc and Mutex for shared state, mpsc for message passing
-use std::thread;                   // For spawning threads
+use std::sync::{Arc, Condvar, Mutex, mpsc};   // Arc, Mutex and Condvar for shared state, mpsc for job results
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering}; // Lock-free counters and flags
+use std::panic::{self, AssertUnwindSafe};     // For surviving jobs that panic
+use std::thread;                              // For spawning threads
+use std::{error, fmt, io, mem};               // For the error types returned by the pool, taking workers out
+use std::time::{Duration, Instant};           // For delayed jobs and pacing auto-grow
+
+pub mod access_log;
+pub mod basic_auth;
+pub mod cookie;
+pub mod cors;
+pub mod error_pages;
+pub mod handler;
+pub mod middleware;
+pub mod mime;
+pub mod multipart;
+pub mod rate_limit;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod static_files;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod websocket;
+
+mod base64;
+mod http_date;
+mod listener;
+mod metrics;
+mod queue;
+mod request_id;
+mod timer;
+#[cfg(feature = "tls")]
+mod tls;
+mod url;
+
+pub use access_log::LogFormat;
+pub use basic_auth::BasicAuth;
+pub use cookie::{CookieOptions, SameSite};
+pub use cors::Cors;
+pub use error_pages::ErrorPage;
+pub use handler::Handler;
+pub use listener::ListenAddr;
+pub use middleware::{Middleware, Timing};
+pub use mime::content_type_for;
+pub use multipart::{MultipartError, Part};
+pub use rate_limit::RateLimit;
+pub use request::{FormError, Method, ParseError, Request, RequestLimits};
+#[cfg(feature = "json")]
+pub use request::JsonError;
+pub use response::Response;
+pub use router::Router;
+pub use server::Server;
+pub use static_files::StaticDir;
+#[cfg(feature = "testutil")]
+pub use testutil::{TestClient, TestResponse};
+pub use websocket::WebSocket;
+
+use queue::{JobQueue, Pop, PushError};
+use timer::Timer;
 
 /// A thread pool for executing jobs concurrently.
-/// 
-/// The ThreadPool manages a set of worker threads and a channel for sending jobs to them.
-#[allow(unused)]
+///
+/// The ThreadPool manages a set of worker threads and a shared priority queue of jobs for them.
+/// When the pool is dropped, the queue is closed and every worker is joined,
+/// so jobs that were already submitted are allowed to finish.
+///
+/// Workers report jobs they pick up and when they stop through the [`log`] crate at debug level,
+/// the pool reports growing at info level, and failures such as a worker that can't be replaced
+/// are warnings or errors; without a logger installed, nothing is printed.
 pub struct ThreadPool {
-    workers: Vec<Worker>,           // Vector holding all worker threads
-    sender: mpsc::Sender<Job>,      // Channel sender to dispatch jobs to workers
+    workers: Arc<Workers>,             // The worker threads, with everything needed to spawn more
+    queue: Arc<JobQueue>,              // Queue the workers pull jobs from
+    state: Arc<PoolState>,             // Bookkeeping shared with the workers
+    timer: Arc<Mutex<Option<Timer>>>,  // Timer thread for delayed jobs, started on first use
 }
 
 /// Type alias for a job that can be executed by the thread pool.
@@ -16,71 +82,1137 @@ pub struct ThreadPool {
 /// but must be Send (can be transferred across threads) and 'static (no borrowed refs).
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// The size of a [`ThreadPool::default`] pool when the number of CPUs can't be found out.
+const FALLBACK_POOL_SIZE: usize = 4;
+
 impl ThreadPool {
     /// Create a new ThreadPool.
-    /// 
+    ///
     /// # Arguments
     /// * `size` - The number of worker threads to spawn in the pool.
-    /// 
+    ///
     /// # Panics
-    /// Panics if `size` is zero.
+    /// Panics if `size` is zero or a worker thread cannot be spawned.
+    /// Use [`ThreadPool::build`] to handle those cases instead.
     pub fn new(size: usize) -> ThreadPool {
-        // Ensure the pool has at least one thread.
-        assert!(size > 0);
-
-        // Create a channel for sending jobs to workers.
-        let (sender, receiver) = mpsc::channel();
-        // Wrap the receiver in Arc<Mutex<...>> so it can be shared and safely accessed by multiple threads.
-        let receiver = Arc::new(Mutex::new(receiver));
-        let mut workers = Vec::with_capacity(size);
+        ThreadPool::build(size).unwrap()
+    }
 
-        // Spawn the specified number of worker threads.
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
-        }
+    /// Create a new ThreadPool, returning an error instead of panicking on invalid input.
+    ///
+    /// This is shorthand for `ThreadPoolBuilder::new(size).build()`.
+    ///
+    /// # Arguments
+    /// * `size` - The number of worker threads to spawn in the pool.
+    ///
+    /// # Errors
+    /// Returns [`PoolCreationError::ZeroSize`] if `size` is zero, or
+    /// [`PoolCreationError::Spawn`] if a worker thread could not be started.
+    pub fn build(size: usize) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPoolBuilder::new(size).build()
+    }
 
-        ThreadPool { workers, sender }
+    /// Create a new ThreadPool whose job queue holds at most `queue_cap` waiting jobs.
+    ///
+    /// When the queue is full, [`ThreadPool::execute`] blocks until a worker frees a slot
+    /// and [`ThreadPool::try_execute`] returns [`TryExecuteError::Full`] straight away.
+    /// Pools created with [`ThreadPool::new`] keep an unbounded queue and never block.
+    ///
+    /// # Arguments
+    /// * `size` - The number of worker threads to spawn in the pool.
+    /// * `queue_cap` - The maximum number of jobs waiting for a worker.
+    ///
+    /// # Panics
+    /// Panics if `size` is zero or a worker thread cannot be spawned.
+    pub fn with_capacity(size: usize, queue_cap: usize) -> ThreadPool {
+        ThreadPoolBuilder::new(size).queue_capacity(queue_cap).build().unwrap()
     }
 
     /// Execute a job (closure) on the thread pool.
-    /// 
+    ///
+    /// # Arguments
+    /// * `f` - The closure or function to execute. Must be Send and 'static.
+    ///
+    /// If the pool was created with a bounded queue and the queue is full,
+    /// this blocks until a worker takes a job off the queue.
+    ///
+    /// # Errors
+    /// Returns an [`ExecuteError`] holding the job if the pool has been shut down
+    /// and can no longer schedule work.
+    pub fn execute<F>(&self, f: F) -> Result<(), ExecuteError>
+    where F: FnOnce() + Send + 'static, {
+        self.execute_with_priority(0, f)
+    }
+
+    /// Execute a job on the thread pool ahead of lower-priority jobs that are still waiting.
+    ///
+    /// Jobs with a higher `priority` are handed to workers first; jobs with the same
+    /// priority run in the order they were submitted. [`ThreadPool::execute`] uses priority 0.
+    /// Jobs that are already running are never interrupted.
+    ///
     /// # Arguments
+    /// * `priority` - The job's priority; higher values run sooner.
     /// * `f` - The closure or function to execute. Must be Send and 'static.
-    pub fn execute<F>(&self, f: F)
+    ///
+    /// # Errors
+    /// Returns an [`ExecuteError`] holding the job if the pool has been shut down
+    /// and can no longer schedule work.
+    pub fn execute_with_priority<F>(&self, priority: u8, f: F) -> Result<(), ExecuteError>
     where F: FnOnce() + Send + 'static, {
         // Box the closure to fit the Job type.
-        let job = Box::new(f);
-        // Send the job to the worker threads via the channel.
-        self.sender.send(job).unwrap();
+        let job: Job = Box::new(f);
+
+        // Push the job onto the queue for the worker threads.
+        // If the queue has been closed, hand the job back to the caller.
+        self.workers
+            .dispatch(priority, job)
+            .map_err(|job| ExecuteError { job })
+    }
+
+    /// Execute a job that computes a value, returning a handle to retrieve the value later.
+    ///
+    /// # Arguments
+    /// * `f` - The closure or function to execute. Must be Send and 'static, as must its result.
+    ///
+    /// If the pool has shut down, or the job panics, the job's result never arrives and
+    /// [`JobHandle::recv`] returns an error instead.
+    pub fn submit<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        // A one-shot channel carries the result back to the handle.
+        let (sender, receiver) = mpsc::channel();
+
+        // If the pool won't take the job, dropping it drops the sender, which the handle reports.
+        let _ = self.execute(move || {
+            // The handle may have been dropped, in which case nobody wants the result.
+            let _ = sender.send(f());
+        });
+
+        JobHandle { receiver }
+    }
+
+    /// Execute a job on the thread pool once `delay` has elapsed.
+    ///
+    /// Delayed jobs are held by a dedicated timer thread (started the first time this is called)
+    /// and only reach the job queue when they are due, so no worker sleeps while waiting.
+    /// A zero delay dispatches the job immediately, exactly like [`ThreadPool::execute`].
+    ///
+    /// Jobs still waiting for their delay are not counted by [`ThreadPool::join`] or
+    /// [`ThreadPool::stats`], and are discarded if the pool shuts down before they are due.
+    /// A delay too long to add to the current time, such as [`Duration::MAX`], doesn't panic:
+    /// the job is held for a century, in practice until the pool shuts down.
+    ///
+    /// # Arguments
+    /// * `delay` - How long to wait before the job is queued.
+    /// * `f` - The closure or function to execute. Must be Send and 'static.
+    ///
+    /// # Errors
+    /// Returns an [`ExecuteError`] holding the job if the pool has been shut down,
+    /// or if the timer thread could not be started.
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> Result<(), ExecuteError>
+    where F: FnOnce() + Send + 'static, {
+        if delay.is_zero() {
+            return self.execute(f);
+        }
+        schedule_delayed(&self.workers, &self.timer, delay, Box::new(f))
+    }
+
+    /// Execute a job on the thread pool without blocking.
+    ///
+    /// # Arguments
+    /// * `f` - The closure or function to execute. Must be Send and 'static.
+    ///
+    /// # Errors
+    /// Returns [`TryExecuteError::Full`] if the bounded queue has no free slot, or
+    /// [`TryExecuteError::ShutDown`] if the pool can no longer schedule work.
+    /// Either way the job is handed back inside the error.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), TryExecuteError>
+    where F: FnOnce() + Send + 'static, {
+        let job: Job = Box::new(f);
+
+        self.workers.try_dispatch(0, job).map_err(|e| match e {
+            PushError::Full(job) => TryExecuteError::Full(job),
+            PushError::Closed(job) => TryExecuteError::ShutDown(job),
+        })
+    }
+
+    /// The number of jobs each worker has run so far, indexed by worker id.
+    ///
+    /// Workers removed by [`ThreadPool::resize`] keep their entry, so the sum is the number of
+    /// jobs the pool has run (including ones that panicked). Like [`ThreadPool::stats`], the
+    /// counters are read without locking.
+    pub fn per_worker_counts(&self) -> Vec<u64> {
+        self.workers
+            .set
+            .lock()
+            .unwrap()
+            .tallies
+            .iter()
+            .map(|tally| tally.jobs_run.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// The ids of the workers currently taking jobs, in the order they were spawned.
+    ///
+    /// Workers that exited, or were told to stop by [`ThreadPool::resize`], aren't included.
+    pub fn worker_ids(&self) -> Vec<usize> {
+        self.workers.set.lock().unwrap().active.iter().map(|worker| worker.id).collect()
+    }
+
+    /// The status of every worker the pool has spawned, indexed by worker id.
+    ///
+    /// Workers that have exited stay in the list with `is_alive` false, whether they were
+    /// stopped, went idle or were taken down by a panicking job (see
+    /// [`ThreadPoolBuilder::catch_panics`]). Like [`ThreadPool::stats`], the values are read
+    /// without locking.
+    pub fn workers_status(&self) -> Vec<WorkerStatus> {
+        self.workers
+            .set
+            .lock()
+            .unwrap()
+            .tallies
+            .iter()
+            .enumerate()
+            .map(|(id, tally)| WorkerStatus {
+                id,
+                jobs_done: tally.jobs_run.load(Ordering::Relaxed),
+                is_alive: tally.alive.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    /// Get a handle that submits jobs to this pool and can be cloned and moved to other threads.
+    ///
+    /// Submitters don't keep the workers alive: dropping (or shutting down) the pool still stops
+    /// it, after which every submitter's [`Submitter::execute`] returns an [`ExecuteError`].
+    pub fn submitter(&self) -> Submitter {
+        Submitter { workers: Arc::clone(&self.workers), timer: Arc::clone(&self.timer) }
+    }
+
+    /// Block until every job submitted so far has finished running.
+    ///
+    /// Unlike [`ThreadPool::shutdown`], the pool stays alive and can be reused afterwards.
+    /// Returns immediately if the pool is idle.
+    pub fn join(&self) {
+        self.state.wait_until_idle();
+    }
+
+    /// Take a snapshot of how busy the pool currently is.
+    ///
+    /// The counters are read without locking, so under load the values are a close
+    /// approximation rather than an exact, atomic view of the pool.
+    pub fn stats(&self) -> PoolStats {
+        self.state.stats()
+    }
+
+    /// The number of active worker threads in the pool.
+    ///
+    /// With an idle timeout or a maximum size configured this changes on its own as idle
+    /// workers exit and are respawned, or as the pool grows under load.
+    pub fn size(&self) -> usize {
+        self.workers.set.lock().unwrap().active.len()
+    }
+
+    /// Grow or shrink the pool to `new_size` worker threads.
+    ///
+    /// Growing spawns new workers straight away. Shrinking tells the most recently
+    /// added workers to stop; a worker that is running a job finishes it first,
+    /// and jobs still in the queue are picked up by the remaining workers.
+    /// This does not wait for the stopped workers to exit.
+    ///
+    /// # Errors
+    /// Returns [`PoolCreationError::ZeroSize`] if `new_size` is zero, or
+    /// [`PoolCreationError::Spawn`] if a new worker thread could not be started.
+    /// Workers spawned before a failure are kept.
+    pub fn resize(&mut self, new_size: usize) -> Result<(), PoolCreationError> {
+        if new_size == 0 {
+            return Err(PoolCreationError::ZeroSize);
+        }
+
+        self.workers.resize(new_size).map_err(PoolCreationError::Spawn)
+    }
+
+    /// Shut the pool down gracefully.
+    ///
+    /// Closing the queue makes every worker exit once the remaining queued jobs have been drained.
+    /// Each worker thread is then joined so no job is lost.
+    /// Any call to `execute` after this returns an [`ExecuteError`].
+    pub fn shutdown(&mut self) {
+        // Close the queue so idle workers stop waiting for new jobs.
+        self.close();
+
+        // Join outside the lock, which a worker may still need while it winds down.
+        // Workers that were already joined by an earlier shutdown are gone from the set.
+        for mut worker in self.workers.take_all() {
+            if let Some(thread) = worker.thread.take() {
+                log::debug!("Shutting down worker {}", worker.id);
+                // A worker taken down by a panicking job has already reported it.
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// Shut the pool down without waiting for jobs still running: the queue is closed, and
+    /// the workers are left to finish and exit on their own, or end with the process.
+    pub(crate) fn abandon(self) {
+        self.close();
+
+        // Dropping the join handles detaches the threads; the shutdown run when the pool
+        // is dropped then finds no workers left to join.
+        drop(self.workers.take_all());
+    }
+
+    /// Close the queue and stop the timer thread, discarding delayed jobs that aren't due yet.
+    fn close(&self) {
+        // The queue is closed as the timer is taken out, under its lock, so a submitter can't
+        // start a new timer in between. The timer is only joined once the lock is released:
+        // its thread may be waiting for room in a full bounded queue until the close wakes it,
+        // and a job scheduling a delayed one of its own may be waiting for the lock.
+        let timer = {
+            let mut timer = self.timer.lock().unwrap();
+            self.queue.close();
+            timer.take()
+        };
+        if let Some(timer) = timer {
+            timer.stop();
+        }
+    }
+}
+
+impl Default for ThreadPool {
+    /// A pool with one worker per CPU the process may use, as reported by
+    /// [`thread::available_parallelism`], or `FALLBACK_POOL_SIZE` workers if that isn't known.
+    ///
+    /// # Panics
+    /// Panics if a worker thread cannot be spawned.
+    fn default() -> ThreadPool {
+        let size = thread::available_parallelism().map_or(FALLBACK_POOL_SIZE, usize::from);
+        ThreadPool::new(size)
+    }
+}
+
+impl Drop for ThreadPool {
+    /// Shut the pool down when it goes out of scope (see [`ThreadPool::shutdown`]).
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A handle to the result of a job started with [`ThreadPool::submit`].
+#[derive(Debug)]
+pub struct JobHandle<T> {
+    receiver: mpsc::Receiver<T>, // Receives the job's return value once it has run
+}
+
+impl<T> JobHandle<T> {
+    /// Block until the job has run and return its result.
+    ///
+    /// # Errors
+    /// Returns [`mpsc::RecvError`] if the job will never produce a result,
+    /// because it panicked or because the pool shut down before running it.
+    pub fn recv(self) -> Result<T, mpsc::RecvError> {
+        self.receiver.recv()
+    }
+}
+
+/// A cheaply cloneable handle for submitting jobs to a [`ThreadPool`] from anywhere.
+///
+/// Obtained with [`ThreadPool::submitter`]. Jobs submitted through it are scheduled exactly
+/// like those submitted to the pool directly, and are counted by [`ThreadPool::join`] and
+/// [`ThreadPool::stats`]. The pool's lifetime is still tied to the [`ThreadPool`] value itself.
+#[derive(Clone)]
+pub struct Submitter {
+    workers: Arc<Workers>,            // The pool's workers, queue and bookkeeping
+    timer: Arc<Mutex<Option<Timer>>>, // The pool's timer thread, shared with it
+}
+
+impl Submitter {
+    /// Execute a job on the pool; see [`ThreadPool::execute`].
+    ///
+    /// # Errors
+    /// Returns an [`ExecuteError`] holding the job if the pool has been shut down.
+    pub fn execute<F>(&self, f: F) -> Result<(), ExecuteError>
+    where F: FnOnce() + Send + 'static, {
+        self.execute_with_priority(0, f)
+    }
+
+    /// Execute a job on the pool ahead of lower-priority jobs; see [`ThreadPool::execute_with_priority`].
+    ///
+    /// # Errors
+    /// Returns an [`ExecuteError`] holding the job if the pool has been shut down.
+    pub fn execute_with_priority<F>(&self, priority: u8, f: F) -> Result<(), ExecuteError>
+    where F: FnOnce() + Send + 'static, {
+        self.workers
+            .dispatch(priority, Box::new(f))
+            .map_err(|job| ExecuteError { job })
+    }
+
+    /// Execute a job on the pool once `delay` has elapsed; see [`ThreadPool::execute_after`],
+    /// including for delays too long to add to the current time.
+    ///
+    /// # Errors
+    /// Returns an [`ExecuteError`] holding the job if the pool has been shut down,
+    /// or if the timer thread could not be started.
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> Result<(), ExecuteError>
+    where F: FnOnce() + Send + 'static, {
+        if delay.is_zero() {
+            return self.execute(f);
+        }
+        schedule_delayed(&self.workers, &self.timer, delay, Box::new(f))
+    }
+}
+
+/// Hand `job` to the pool's timer thread, starting it if it isn't running yet.
+///
+/// The queue is checked under the timer's lock, which shutting down holds while it closes the
+/// queue, so a timer is never started for a pool that has already stopped its own.
+fn schedule_delayed(
+    workers: &Arc<Workers>,
+    timer: &Mutex<Option<Timer>>,
+    delay: Duration,
+    job: Job,
+) -> Result<(), ExecuteError> {
+    let mut timer = timer.lock().unwrap();
+    if workers.queue.is_closed() {
+        return Err(ExecuteError { job });
+    }
+
+    if timer.is_none() {
+        match Timer::start(Arc::clone(workers)) {
+            Ok(started) => *timer = Some(started),
+            Err(e) => {
+                log::error!("Failed to start the timer thread: {e}");
+                return Err(ExecuteError { job });
+            }
+        }
+    }
+
+    timer.as_ref().unwrap().schedule(delay, job);
+    Ok(())
+}
+
+impl fmt::Debug for Submitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Submitter").finish_non_exhaustive()
+    }
+}
+
+/// Configures and creates a [`ThreadPool`].
+///
+/// Options that are not set fall back to the same defaults used by [`ThreadPool::new`].
+#[derive(Debug, Clone)]
+pub struct ThreadPoolBuilder {
+    size: usize,                    // Number of worker threads to spawn
+    stack_size: Option<usize>,      // Stack size in bytes for each worker (None = OS default)
+    thread_name: String,            // Worker threads are named this, a dash and their id
+    catch_panics: bool,             // Whether a worker survives a job that panics
+    queue_capacity: Option<usize>,  // Maximum number of waiting jobs (None = unbounded)
+    work_stealing: bool,            // Whether workers keep their own deques and steal from each other
+    idle_timeout: Option<Duration>, // How long a worker may sit idle before exiting (None = forever)
+    min_workers: usize,             // Workers kept alive however idle the pool is
+    max_workers: Option<usize>,     // Workers the pool may grow to under pressure (None = no growth)
+    high_water_mark: Option<usize>, // Queue depth counted as pressure (None = the pool's size)
+}
+
+impl ThreadPoolBuilder {
+    /// Start configuring a pool with `size` worker threads.
+    pub fn new(size: usize) -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            size,
+            stack_size: None,
+            thread_name: "worker".to_owned(),
+            catch_panics: true,
+            queue_capacity: None,
+            work_stealing: false,
+            idle_timeout: None,
+            min_workers: 1,
+            max_workers: None,
+            high_water_mark: None,
+        }
+    }
+
+    /// Set the stack size, in bytes, of every worker thread.
+    ///
+    /// Useful for jobs that recurse deeply and would overflow the default stack.
+    pub fn stack_size(mut self, bytes: usize) -> ThreadPoolBuilder {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Name worker threads `{prefix}-{id}`, as seen in debuggers and panic messages.
+    /// Defaults to `worker`, giving `worker-0`, `worker-1` and so on.
+    pub fn thread_name(mut self, prefix: &str) -> ThreadPoolBuilder {
+        self.thread_name = prefix.to_owned();
+        self
+    }
+
+    /// Whether a worker carries on with the next job after one panics. Defaults to `true`.
+    ///
+    /// Either way the panic is counted in [`PoolStats::panicked_jobs`] and the job counts as
+    /// finished. When disabled, the panic ends the worker's thread, dropping its thread-local
+    /// state, and a new worker is spawned in its place.
+    pub fn catch_panics(mut self, enabled: bool) -> ThreadPoolBuilder {
+        self.catch_panics = enabled;
+        self
+    }
+
+    /// Bound the job queue to at most `jobs` waiting jobs.
+    ///
+    /// See [`ThreadPool::with_capacity`] for how a full queue behaves.
+    /// A capacity of zero means every job is handed directly to an idle worker.
+    pub fn queue_capacity(mut self, jobs: usize) -> ThreadPoolBuilder {
+        self.queue_capacity = Some(jobs);
+        self
+    }
+
+    /// Schedule jobs by work stealing instead of through one shared priority queue.
+    ///
+    /// New jobs go into a global queue. Each worker moves batches of them into a deque of its own
+    /// and runs them from there, and once its deque is empty it steals from the other workers.
+    /// Workers only contend on a lock when there's nothing to do, which helps with many short jobs.
+    ///
+    /// In this mode priorities are ignored: [`ThreadPool::execute_with_priority`] behaves like
+    /// [`ThreadPool::execute`], and jobs run in roughly, but not exactly, the order they were submitted.
+    pub fn work_stealing(mut self, enabled: bool) -> ThreadPoolBuilder {
+        self.work_stealing = enabled;
+        self
+    }
+
+    /// Let workers exit once they have been idle for `timeout`, down to [`ThreadPoolBuilder::min_workers`].
+    ///
+    /// This keeps a mostly idle pool small. When a job arrives and no worker is idle, a worker
+    /// is spawned again, up to the pool's size (as set here or by [`ThreadPool::resize`]).
+    pub fn idle_timeout(mut self, timeout: Duration) -> ThreadPoolBuilder {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// The number of workers kept alive by [`ThreadPoolBuilder::idle_timeout`]. Defaults to 1.
+    ///
+    /// A minimum of zero lets every worker exit; one is spawned again when a job arrives.
+    /// A minimum above the pool's size keeps every worker alive.
+    pub fn min_workers(mut self, workers: usize) -> ThreadPoolBuilder {
+        self.min_workers = workers;
+        self
+    }
+
+    /// Let the pool grow to `workers` threads while jobs pile up in the queue.
+    ///
+    /// Whenever more jobs than the [`ThreadPoolBuilder::high_water_mark`] have been waiting for
+    /// at least `GROW_INTERVAL`, submitting another job spawns one more worker, at most once per
+    /// `GROW_INTERVAL`. Extra workers stay until the pool is resized, or, with an
+    /// [`ThreadPoolBuilder::idle_timeout`], until they go idle.
+    /// A maximum no larger than the pool's size disables growth.
+    pub fn max_workers(mut self, workers: usize) -> ThreadPoolBuilder {
+        self.max_workers = Some(workers);
+        self
+    }
+
+    /// The number of waiting jobs above which [`ThreadPoolBuilder::max_workers`] grows the pool.
+    /// Defaults to the pool's size.
+    pub fn high_water_mark(mut self, jobs: usize) -> ThreadPoolBuilder {
+        self.high_water_mark = Some(jobs);
+        self
+    }
+
+    /// Spawn the worker threads and return the configured pool.
+    ///
+    /// # Errors
+    /// Returns [`PoolCreationError::ZeroSize`] if the size is zero, or
+    /// [`PoolCreationError::Spawn`] if the operating system refused to start a worker thread.
+    pub fn build(self) -> Result<ThreadPool, PoolCreationError> {
+        // Ensure the pool has at least one thread.
+        if self.size == 0 {
+            return Err(PoolCreationError::ZeroSize);
+        }
+
+        let queue = Arc::new(JobQueue::new(self.queue_capacity, self.work_stealing));
+        let state = Arc::new(PoolState::new());
+        let idle_shrink = self.idle_timeout.map(|timeout| IdleShrink { timeout, min_workers: self.min_workers });
+        let auto_grow = self.max_workers.map(|max_workers| AutoGrow {
+            high_water_mark: self.high_water_mark.unwrap_or(self.size),
+            max_workers,
+        });
+
+        // Build the pool up front so that, if a spawn fails part way through,
+        // dropping it shuts down the workers that did start.
+        let pool = ThreadPool {
+            workers: Arc::new(Workers {
+                queue: Arc::clone(&queue),
+                state: Arc::clone(&state),
+                stack_size: self.stack_size,
+                thread_name: self.thread_name,
+                catch_panics: self.catch_panics,
+                idle_shrink,
+                auto_grow,
+                set: Mutex::new(WorkerSet {
+                    active: Vec::with_capacity(self.size),
+                    retired: Vec::new(),
+                    target: 0,
+                    next_id: 0,
+                    tallies: Vec::with_capacity(self.size),
+                    pressure_since: None,
+                    last_grow: None,
+                }),
+            }),
+            queue,
+            state,
+            timer: Arc::new(Mutex::new(None)),
+        };
+
+        // Spawn the specified number of worker threads.
+        pool.workers.resize(self.size).map_err(PoolCreationError::Spawn)?;
+
+        Ok(pool)
     }
 }
 
+/// Error returned by [`ThreadPool::execute`] when a job could not be scheduled.
+///
+/// The rejected job is kept inside the error so the caller can retry it elsewhere or drop it.
+pub struct ExecuteError {
+    job: Job, // The job that could not be sent to the workers
+}
+
+impl ExecuteError {
+    /// Recover the job that could not be scheduled.
+    pub fn into_job(self) -> Box<dyn FnOnce() + Send + 'static> {
+        self.job
+    }
+}
+
+// Closures don't implement Debug, so only the type name is printed.
+impl fmt::Debug for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecuteError").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the thread pool has shut down and cannot schedule the job")
+    }
+}
+
+impl error::Error for ExecuteError {}
+
+/// Error returned by [`ThreadPool::try_execute`] when a job could not be scheduled immediately.
+///
+/// Both variants carry the rejected job so it can be retried or dropped.
+pub enum TryExecuteError {
+    /// The bounded job queue is full.
+    Full(Job),
+    /// The pool has shut down and will not accept any more work.
+    ShutDown(Job),
+}
+
+impl TryExecuteError {
+    /// Recover the job that could not be scheduled.
+    pub fn into_job(self) -> Box<dyn FnOnce() + Send + 'static> {
+        match self {
+            TryExecuteError::Full(job) | TryExecuteError::ShutDown(job) => job,
+        }
+    }
+}
+
+impl fmt::Debug for TryExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryExecuteError::Full(_) => f.write_str("Full(..)"),
+            TryExecuteError::ShutDown(_) => f.write_str("ShutDown(..)"),
+        }
+    }
+}
+
+impl fmt::Display for TryExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryExecuteError::Full(_) => write!(f, "the thread pool's job queue is full"),
+            TryExecuteError::ShutDown(_) => {
+                write!(f, "the thread pool has shut down and cannot schedule the job")
+            }
+        }
+    }
+}
+
+impl error::Error for TryExecuteError {}
+
+/// Error returned by [`ThreadPool::build`] when the pool could not be created.
+#[derive(Debug)]
+pub enum PoolCreationError {
+    /// The requested pool size was zero; a pool needs at least one worker.
+    ZeroSize,
+    /// A worker thread could not be spawned.
+    Spawn(io::Error),
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolCreationError::ZeroSize => {
+                write!(f, "a thread pool must have at least one worker thread")
+            }
+            PoolCreationError::Spawn(e) => write!(f, "failed to spawn a worker thread: {e}"),
+        }
+    }
+}
+
+impl error::Error for PoolCreationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PoolCreationError::ZeroSize => None,
+            PoolCreationError::Spawn(e) => Some(e),
+        }
+    }
+}
+
+/// A point-in-time view of pool activity, returned by [`ThreadPool::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Jobs currently being executed by a worker.
+    pub active_jobs: usize,
+    /// Jobs waiting in the queue for a free worker.
+    pub queued_jobs: usize,
+    /// Jobs that have finished running since the pool was created, including ones that panicked.
+    pub total_completed: u64,
+    /// Jobs that panicked while running since the pool was created.
+    pub panicked_jobs: u64,
+}
+
+/// One worker's entry in [`ThreadPool::workers_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerStatus {
+    /// The worker's id, which is also the number at the end of its thread's name.
+    pub id: usize,
+    /// Jobs the worker has run, including ones that panicked.
+    pub jobs_done: u64,
+    /// Whether the worker's thread is still running.
+    pub is_alive: bool,
+}
+
+/// Bookkeeping shared between the pool and its workers.
+struct PoolState {
+    pending: Mutex<usize>, // Jobs submitted but not yet finished (queued or running)
+    idle: Condvar,         // Signalled whenever `pending` drops to zero
+    submitted: AtomicU64,  // Jobs sent to the queue
+    started: AtomicU64,    // Jobs picked up by a worker
+    completed: AtomicU64,  // Jobs that finished running
+    panicked: AtomicU64,   // Jobs that panicked while running
+}
+
+impl PoolState {
+    fn new() -> PoolState {
+        PoolState {
+            pending: Mutex::new(0),
+            idle: Condvar::new(),
+            submitted: AtomicU64::new(0),
+            started: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+        }
+    }
+
+    /// Push a job onto the queue, keeping the counters in step.
+    ///
+    /// Hands the job back if the queue has been closed.
+    /// Returns whether a worker was waiting to take the job.
+    fn dispatch(&self, queue: &JobQueue, priority: u8, job: Job) -> Result<bool, Job> {
+        // Count the job before pushing it so `join` can never miss it.
+        self.job_submitted();
+
+        // Undo the count for a job that never reached the queue.
+        queue.push(priority, job).inspect_err(|_| self.job_rejected())
+    }
+
+    /// Record that a job has been handed to the queue.
+    fn job_submitted(&self) {
+        self.submitted.fetch_add(1, Ordering::SeqCst);
+        *self.pending.lock().unwrap() += 1;
+    }
+
+    /// Undo `job_submitted` for a job that never made it into the queue.
+    fn job_rejected(&self) {
+        self.submitted.fetch_sub(1, Ordering::SeqCst);
+        self.job_done();
+    }
+
+    /// Record that a worker has picked a job up.
+    fn job_started(&self) {
+        self.started.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Record that a job is finished running.
+    fn job_finished(&self) {
+        self.completed.fetch_add(1, Ordering::SeqCst);
+        self.job_done();
+    }
+
+    /// Record that a job panicked while running.
+    fn job_panicked(&self) {
+        self.panicked.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Drop a job from the pending count, waking anyone in `join` if the pool became idle.
+    fn job_done(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        *pending -= 1;
+        if *pending == 0 {
+            self.idle.notify_all();
+        }
+    }
+
+    /// Block until no jobs are queued or running.
+    fn wait_until_idle(&self) {
+        let pending = self.pending.lock().unwrap();
+        let _idle = self.idle.wait_while(pending, |pending| *pending > 0).unwrap();
+    }
+
+    fn stats(&self) -> PoolStats {
+        // Read in the reverse order of updates so each count is never behind the one after it.
+        let completed = self.completed.load(Ordering::SeqCst);
+        let started = self.started.load(Ordering::SeqCst);
+        let submitted = self.submitted.load(Ordering::SeqCst);
+
+        PoolStats {
+            active_jobs: started.saturating_sub(completed) as usize,
+            queued_jobs: submitted.saturating_sub(started) as usize,
+            total_completed: completed,
+            panicked_jobs: self.panicked.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// The pool's worker threads, shared with everything that may spawn or retire a worker:
+/// the pool itself, its submitters, the timer thread, and the workers themselves.
+struct Workers {
+    queue: Arc<JobQueue>,             // Queue the workers pull jobs from
+    state: Arc<PoolState>,            // Bookkeeping shared with the workers
+    stack_size: Option<usize>,        // Stack size for worker threads (None = OS default)
+    thread_name: String,              // Prefix of each worker thread's name
+    catch_panics: bool,               // Whether workers survive jobs that panic
+    idle_shrink: Option<IdleShrink>,  // When idle workers exit (None = never)
+    auto_grow: Option<AutoGrow>,      // When the pool grows under load (None = never)
+    set: Mutex<WorkerSet>,            // The threads themselves
+}
+
+/// The threads managed by [`Workers`], kept under one lock so counts stay consistent.
+struct WorkerSet {
+    active: Vec<Worker>,             // Workers currently taking jobs
+    retired: Vec<Worker>,            // Workers told to stop that may still be finishing a job
+    target: usize,                   // The pool's size, as last set by `build` or `resize`
+    next_id: usize,                  // Id given to the next spawned worker
+    tallies: Vec<Arc<WorkerTally>>,  // Counters of each worker ever spawned, indexed by worker id
+    pressure_since: Option<Instant>, // When the queue last rose above the high-water mark, if it still is
+    last_grow: Option<Instant>,      // When auto-grow last spawned a worker
+}
+
+/// What a worker reports about itself, kept after it exits; see [`ThreadPool::workers_status`].
+struct WorkerTally {
+    jobs_run: AtomicU64, // Jobs the worker has started
+    alive: AtomicBool,   // Cleared as the worker's thread ends, even by a panic
+}
+
+/// How idle workers are retired; see [`ThreadPoolBuilder::idle_timeout`].
+#[derive(Debug, Clone, Copy)]
+struct IdleShrink {
+    timeout: Duration,  // How long a worker waits for a job before exiting
+    min_workers: usize, // Workers that never exit for being idle
+}
+
+/// How the pool grows under load; see [`ThreadPoolBuilder::max_workers`].
+#[derive(Debug, Clone, Copy)]
+struct AutoGrow {
+    high_water_mark: usize, // Waiting jobs above which the queue is under pressure
+    max_workers: usize,     // Workers the pool may grow to
+}
+
+/// How long the queue must stay above its high-water mark before the pool grows,
+/// and the least time between two grows, so a short burst doesn't spawn a crowd of workers.
+const GROW_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Workers {
+    /// Push a job onto the queue, keeping the counters in step and respawning a worker
+    /// if idle ones have exited and none is free to take it.
+    ///
+    /// Hands the job back if the queue has been closed.
+    fn dispatch(self: &Arc<Workers>, priority: u8, job: Job) -> Result<(), Job> {
+        let woke_idle = self.state.dispatch(&self.queue, priority, job)?;
+        if !woke_idle && self.idle_shrink.is_some() {
+            self.respawn();
+        }
+        self.grow_if_pressured();
+        Ok(())
+    }
+
+    /// Like `dispatch`, but never waits for room in a bounded queue.
+    fn try_dispatch(self: &Arc<Workers>, priority: u8, job: Job) -> Result<(), PushError> {
+        // Count the job before pushing it so `join` can never miss it.
+        self.state.job_submitted();
+        let woke_idle = self
+            .queue
+            .try_push(priority, job)
+            .inspect_err(|_| self.state.job_rejected())?;
+
+        if !woke_idle && self.idle_shrink.is_some() {
+            self.respawn();
+        }
+        self.grow_if_pressured();
+        Ok(())
+    }
+
+    /// Spawn one more worker if the queue has stayed above its high-water mark
+    /// for `GROW_INTERVAL` and the pool may still grow.
+    fn grow_if_pressured(self: &Arc<Workers>) {
+        let Some(auto_grow) = self.auto_grow else {
+            return;
+        };
+
+        let depth = self.queue.pending();
+        let mut set = self.set.lock().unwrap();
+        if depth <= auto_grow.high_water_mark {
+            set.pressure_since = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let pressure_since = *set.pressure_since.get_or_insert(now);
+        let sustained = now.duration_since(pressure_since) >= GROW_INTERVAL;
+        let rested = set.last_grow.is_none_or(|last| now.duration_since(last) >= GROW_INTERVAL);
+        if !sustained || !rested || set.active.len() >= auto_grow.max_workers {
+            return;
+        }
+
+        set.reap_retired();
+        match self.spawn(&mut set) {
+            Ok(()) => {
+                set.last_grow = Some(now);
+                log::info!("{depth} jobs queued, growing to {} workers.", set.active.len());
+            }
+            // The pool keeps working with the workers it has.
+            Err(e) => log::warn!("Failed to grow the pool: {e}"),
+        }
+    }
+
+    /// Grow or shrink to `new_size` workers; see [`ThreadPool::resize`].
+    ///
+    /// # Errors
+    /// Returns the io error if a new worker thread could not be started.
+    /// Workers spawned before a failure are kept.
+    fn resize(self: &Arc<Workers>, new_size: usize) -> io::Result<()> {
+        let mut set = self.set.lock().unwrap();
+        set.target = new_size;
+
+        // Forget about retired workers whose threads have already exited.
+        set.reap_retired();
+
+        // Grow: spawn additional workers sharing the same job queue.
+        while set.active.len() < new_size {
+            self.spawn(&mut set)?;
+        }
+
+        // Shrink: signal the excess workers and keep them around until they exit.
+        while set.active.len() > new_size {
+            let worker = set.active.pop().unwrap();
+            worker.stop();
+            set.retired.push(worker);
+        }
+        self.queue.wake_workers();
+
+        Ok(())
+    }
+
+    /// Spawn a worker if idle ones have exited and the pool is below its size.
+    fn respawn(self: &Arc<Workers>) {
+        let mut set = self.set.lock().unwrap();
+        set.reap_retired();
+        if set.active.len() < set.target
+            && let Err(e) = self.spawn(&mut set)
+        {
+            // The job is queued either way; the workers that are left will get to it.
+            log::error!("Failed to respawn a worker: {e}");
+        }
+    }
+
+    /// Spawn one more worker and add it to `set`.
+    fn spawn(self: &Arc<Workers>, set: &mut WorkerSet) -> io::Result<()> {
+        let id = set.next_id;
+        let tally = Arc::new(WorkerTally { jobs_run: AtomicU64::new(0), alive: AtomicBool::new(true) });
+        let worker = Worker::new(id, Arc::clone(self), Arc::clone(&tally))?;
+        set.next_id += 1;
+        set.tallies.push(tally);
+        set.active.push(worker);
+        Ok(())
+    }
+
+    /// Called by worker `id` when it timed out waiting for a job: retire it unless the pool
+    /// is already down to its minimum. Returns whether the worker should exit.
+    fn retire_idle(&self, id: usize) -> bool {
+        let Some(idle_shrink) = self.idle_shrink else {
+            return false;
+        };
+
+        // A job pushed since this worker gave up may have found nobody waiting. If the push
+        // came first it shows up here; if not, the pusher sees this worker gone and respawns.
+        let mut set = self.set.lock().unwrap();
+        if set.active.len() <= idle_shrink.min_workers || self.queue.pending() > 0 {
+            return false;
+        }
+        // Not found means the pool is shutting down or already retired this worker.
+        match set.active.iter().position(|worker| worker.id == id) {
+            Some(pos) => {
+                let worker = set.active.remove(pos);
+                worker.stop();
+                set.retired.push(worker);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Called by worker `id` when a job panicked and [`ThreadPoolBuilder::catch_panics`] is off:
+    /// retire it, since its thread is about to end, and spawn a new worker in its place.
+    fn replace(self: &Arc<Workers>, id: usize) {
+        let mut set = self.set.lock().unwrap();
+        // Not found means the pool is shutting down or already retired this worker.
+        let Some(pos) = set.active.iter().position(|worker| worker.id == id) else {
+            return;
+        };
+        let worker = set.active.remove(pos);
+        set.retired.push(worker);
+        if !self.queue.is_closed()
+            && let Err(e) = self.spawn(&mut set)
+        {
+            // The remaining workers carry on with the queue.
+            log::error!("Failed to replace a worker: {e}");
+        }
+    }
+
+    /// Remove every worker, active or retired, so the caller can join them.
+    fn take_all(&self) -> Vec<Worker> {
+        let mut set = self.set.lock().unwrap();
+        let mut workers = mem::take(&mut set.active);
+        workers.append(&mut set.retired);
+        workers
+    }
+}
+
+impl WorkerSet {
+    /// Drop the handles of retired workers that have finished.
+    fn reap_retired(&mut self) {
+        self.retired.retain(|worker| {
+            worker.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+        });
+    }
+}
+
+/// Marks a job as finished when dropped, so the count stays correct even if the job panics.
+struct FinishGuard<'a>(&'a PoolState);
+
+impl Drop for FinishGuard<'_> {
+    fn drop(&mut self) {
+        self.0.job_finished();
+    }
+}
+
+/// Marks a worker as no longer alive when dropped, however its thread ends.
+struct ExitGuard<'a>(&'a AtomicBool);
+
+impl Drop for ExitGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
 
 /// Represents a single worker in the thread pool.
 /// Each worker has a unique id and owns a thread handle.
-#[allow(unused)]
 struct Worker {
-    id: usize,                      // Worker id (for logging/debugging)
-    thread: thread::JoinHandle<()>, // Handle to the spawned thread
+    id: usize,                              // Worker id (for logging/debugging)
+    thread: Option<thread::JoinHandle<()>>, // Handle to the spawned thread (None once joined)
+    stop: Arc<AtomicBool>,                  // Set to tell the worker to exit after its current job
 }
 
 impl Worker {
     /// Create a new worker thread.
-    /// 
+    ///
     /// # Arguments
     /// * `id` - The worker's unique identifier.
-    /// * `receiver` - Shared receiver for jobs, protected by Arc<Mutex<...>>.
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    /// * `workers` - The pool's workers, which hold the job queue to pull jobs from,
+    ///   the bookkeeping updated as jobs finish, and the thread settings.
+    /// * `tally` - Counts the jobs this worker starts, and notes when its thread ends.
+    ///
+    /// # Errors
+    /// Returns the underlying io error if the thread could not be spawned.
+    fn new(id: usize, workers: Arc<Workers>, tally: Arc<WorkerTally>) -> io::Result<Worker> {
+        // Name the thread after the worker so it shows up in debuggers and panic messages.
+        let mut builder = thread::Builder::new().name(format!("{}-{id}", workers.thread_name));
+        if let Some(bytes) = workers.stack_size {
+            builder = builder.stack_size(bytes);
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
+
         // Spawn a new thread that waits for jobs and executes them as they arrive.
-        let thread = thread::spawn(move || {
-            // Loop, waiting for jobs to be received from the channel.
-            while let Ok(job) = receiver.lock().unwrap().recv() {
-                println!("Worker {id} got a job, executing...");
-                job(); // Execute the job (closure)
+        let thread = builder.spawn(move || {
+            let _exit = ExitGuard(&tally.alive);
+            let (queue, state) = (&workers.queue, &workers.state);
+            let idle_timeout = workers.idle_shrink.map(|idle_shrink| idle_shrink.timeout);
+            let local = queue.local(id);
+            let mut idle_exit = false;
+
+            // Wait for a job. Each job is delivered to exactly one worker.
+            loop {
+                let job = match queue.pop(&stop_flag, &local, idle_timeout) {
+                    Pop::Job(job) => job,
+                    // Exit if the pool can spare this worker, otherwise keep waiting.
+                    Pop::Idle if workers.retire_idle(id) => {
+                        idle_exit = true;
+                        break;
+                    }
+                    Pop::Idle => continue,
+                    Pop::Done => break,
+                };
+
+                let current = thread::current();
+                let name = current.name().unwrap_or("unnamed");
+                log::debug!("Worker {id} ({name}) got a job, executing...");
+                state.job_started();
+                tally.jobs_run.fetch_add(1, Ordering::Relaxed);
+                let _finished = FinishGuard(state);
+                // Execute the job (closure), catching a panic so this worker
+                // keeps serving the pool. The job is consumed by the call and
+                // never observed again, so unwind safety is not a concern.
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    state.job_panicked();
+                    if !workers.catch_panics {
+                        log::error!("Worker {id} ({name}) job panicked, replacing the worker.");
+                        workers.replace(id);
+                        panic::resume_unwind(payload);
+                    }
+                    log::error!("Worker {id} ({name}) job panicked, continuing.");
+                }
             }
-        });
-        Worker { id, thread }
-    }
-}
 
+            if idle_exit {
+                // The worker sat idle for too long and the pool has enough without it.
+                log::debug!("Worker {id} idle, shutting down.");
+            } else if stop_flag.load(Ordering::SeqCst) {
+                // The pool was shrunk and this worker is no longer needed.
+                log::debug!("Worker {id} stopped by resize, shutting down.");
+            } else {
+                // The queue has been closed and drained: no more jobs will arrive.
+                log::debug!("Worker {id} disconnected, shutting down.");
+            }
+        })?;
+        Ok(Worker { id, thread: Some(thread), stop })
+    }
 
+    /// Tell the worker to exit once it has finished any job it is running.
+    ///
+    /// The worker only notices once it is woken, see [`JobQueue::wake_workers`].
+    fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
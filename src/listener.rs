@@ -0,0 +1,183 @@
+// The sockets the server listens on and the connections accepted from them.
+use std::fmt;                                      // For displaying listen addresses
+use std::io::{self, Read, Write};                  // For reading from and writing to connections
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs}; // For TCP networking
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;                // For recognising stale socket files
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream}; // For Unix domain sockets
+#[cfg(unix)]
+use std::path::{Path, PathBuf};                    // For socket file locations
+#[cfg(unix)]
+use std::fs;                                       // For removing stale socket files
+
+use socket2::{Domain, Protocol, Socket, Type};     // For choosing the listen backlog
+
+/// Where a [`Server`](crate::Server) is listening.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    /// A TCP address, with the real port if port 0 was asked for.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// The TCP address, or `None` for a Unix socket.
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            ListenAddr::Tcp(addr) => Some(*addr),
+            #[cfg(unix)]
+            ListenAddr::Unix(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    /// TCP addresses are shown as usual, e.g. `127.0.0.1:6969`; Unix sockets as `unix:/run/app.sock`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{addr}"),
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A bound listening socket.
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Bind a TCP socket to the first of `addrs` that can be bound, like `TcpListener::bind`,
+    /// but queueing up to `backlog` connections that haven't been accepted yet.
+    pub(crate) fn bind_tcp_with_backlog(addrs: impl ToSocketAddrs, backlog: i32) -> io::Result<TcpListener> {
+        let mut last_error = None;
+        for addr in addrs.to_socket_addrs()? {
+            match bind_tcp_socket(addr, backlog) {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")))
+    }
+
+    /// Bind a Unix domain socket at `path`.
+    ///
+    /// A socket file left behind by a server that is no longer running is removed first.
+    /// If a server is still accepting connections on it, binding fails with `AddrInUse` instead.
+    #[cfg(unix)]
+    pub(crate) fn bind_unix(path: &Path) -> io::Result<Listener> {
+        let is_socket = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+        if is_socket {
+            match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is already being served", path.display()),
+                    ));
+                }
+                // Nobody is listening, so the file is stale.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)?,
+                Err(_) => {}
+            }
+        }
+
+        UnixListener::bind(path).map(Listener::Unix)
+    }
+
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    pub(crate) fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Connection::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Connection::Unix(stream)),
+        }
+    }
+}
+
+/// Bind and listen on one TCP address with the given backlog.
+fn bind_tcp_socket(addr: SocketAddr, backlog: i32) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // Like `TcpListener::bind`, allow binding a port whose old connections are still in TIME_WAIT.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(backlog)?;
+    Ok(socket.into())
+}
+
+/// An accepted client connection.
+pub(crate) enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Send small writes straight away instead of waiting to batch them (`TCP_NODELAY`).
+    /// Unix sockets don't delay writes, so for them this does nothing.
+    pub(crate) fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.set_nodelay(nodelay),
+            #[cfg(unix)]
+            Connection::Unix(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.shutdown(how),
+        }
+    }
+}
+
+impl Read for &Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Connection::Tcp(stream) => (&*stream).read(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => (&*stream).read(buf),
+        }
+    }
+}
+
+impl Write for &Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Connection::Tcp(stream) => (&*stream).write(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => (&*stream).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Connection::Tcp(stream) => (&*stream).flush(),
+            #[cfg(unix)]
+            Connection::Unix(stream) => (&*stream).flush(),
+        }
+    }
+}
@@ -1,74 +1,164 @@
 // Import necessary modules from the standard library
-use std::net::{TcpListener, TcpStream}; // For TCP networking
-use std::io::{BufReader, prelude::*};   // For buffered reading and I/O traits
-use std::fs;                            // For file system operations
-use std::thread;                        // For thread sleeping (simulated delay)
-use std::time::Duration;                // For specifying sleep duration
-use rust_webserver::ThreadPool;         // Custom thread pool implementation
+use std::env;                           // For reading the bind address from the environment and arguments
+use std::io;                            // For bind errors
+use std::process;                       // For exiting with an error status
+use std::sync::atomic::Ordering;        // For setting the shutdown flag
+use std::time::Duration;                // For the shutdown grace period and the simulated delay
+use rust_webserver::{ErrorPage, Method, Response, Router, Server, ThreadPool}; // HTTP types, server and thread pool
+
+/// The address the server listens on when none is configured.
+const DEFAULT_ADDR: &str = "127.0.0.1:6969";
+
+/// Connections served at once; beyond this, clients get 503 instead of waiting in the queue.
+const MAX_CONNECTIONS: usize = 256;
+
+/// How long Ctrl-C waits for in-flight requests before exiting anyway.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Prints the warnings and errors the server reports through the `log` crate to stderr.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
 
 /// Entry point of the web server application.
 /// 
-/// Binds a TCP listener to localhost on port 6969 and handles incoming connections using a thread pool.
-/// Each incoming TCP stream is processed in a worker thread by calling `handle_connection`.
+/// Binds a `Server` to the address chosen by `bind_address` and serves incoming connections
+/// using a thread pool, routing each request with the table from `routes`.
+/// Exits with a non-zero status if the address is invalid or can't be bound.
+///
+/// Ctrl-C stops accepting new connections, gives in-flight requests up to `SHUTDOWN_GRACE` to finish,
+/// and exits with status 0.
+/// On Unix this is SIGINT; on Windows it is the console's Ctrl-C (and Ctrl-Break) event.
+/// Other ways of stopping the process, such as SIGTERM or closing the console window,
+/// still end it immediately.
 fn main() {
-    // Bind the TCP listener to the specified address and port.
-    // Panics if binding fails (e.g., port already in use).
-    let listener = TcpListener::bind("127.0.0.1:6969").unwrap();
-    // Create a thread pool with 4 worker threads.
-    let pool = ThreadPool::new(4);
-    // Accept incoming connections in a loop.
-    for stream in listener.incoming() {
-        // Unwrap the Result to get the actual TcpStream.
-        // If an error occurs, the server will panic.
-        let stream = stream.unwrap();
-        // Submit the connection to the thread pool for processing.
-        pool.execute(|| {
-            handle_connection(stream);
-        });
+    // Show the server's warnings and errors, such as failed accepts; debug and info messages are dropped.
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    // Pick the address from the environment or the command line.
+    let addr = match bind_address(env::var("SERVER_ADDR").ok(), env::args().skip(1)) {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(2);
+        }
+    };
+    // Bind the listening socket to the chosen address and port, or to a Unix socket for `unix:<PATH>`.
+    // Binding to port 0 picks a free port; the printed address shows which one.
+    let server = match bind(&addr) {
+        Ok(server) => server
+            .router(routes())
+            .max_connections(MAX_CONNECTIONS)
+            .shutdown_grace(SHUTDOWN_GRACE),
+        Err(e) => {
+            eprintln!("Failed to listen on {addr}: {e}");
+            process::exit(1);
+        }
+    };
+    // With the tls feature, serve HTTPS when TLS_CERT and TLS_KEY name a PEM certificate chain and key.
+    #[cfg(feature = "tls")]
+    let server = match (env::var("TLS_CERT"), env::var("TLS_KEY")) {
+        (Ok(cert), Ok(key)) => server.tls(&cert, &key).unwrap_or_else(|e| {
+            eprintln!("Failed to load TLS certificate: {e}");
+            process::exit(1);
+        }),
+        _ => server,
+    };
+    for addr in server.local_addrs() {
+        println!("Listening on {addr}");
+    }
+    // Ask the accept loop to stop on Ctrl-C. If the handler can't be installed, Ctrl-C just kills the process.
+    let shutdown = server.shutdown_handle();
+    if let Err(e) = ctrlc::set_handler(move || shutdown.store(true, Ordering::SeqCst)) {
+        eprintln!("Failed to install Ctrl-C handler: {e}");
+    }
+    // Create a thread pool with 4 worker threads and hand it to the accept loop.
+    if let Err(e) = server.run(ThreadPool::new(4)) {
+        eprintln!("Server stopped: {e}");
+        process::exit(1);
     }
+    println!("Shut down cleanly");
 }
 
-/// Handles an individual TCP connection by reading the HTTP request and sending an appropriate response.
-/// 
-/// # Arguments
-/// * `stream` - The TCP stream representing the client connection.
-/// 
-/// Reads the first line of the HTTP request, determines the requested path, and serves the appropriate HTML file.
-/// - For `GET /`, serves `pages/hello.html` with 200 OK.
-/// - For `GET /sleep`, waits 5 seconds then serves `pages/hello.html` with 200 OK.
-/// - For any other path, serves `pages/404.html` with 404 NOT FOUND.
-/// The response includes the HTTP status line, Content-Length header, and the file contents as the body.
-fn handle_connection(mut stream: TcpStream) {
-    // Wrap the stream in a buffered reader for efficient line-by-line reading.
-    let buf_reader = BufReader::new(&stream);
-
-    // Read the first line of the HTTP request (the request line).
-    // Example: "GET / HTTP/1.1"
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
+/// Binds a Unix socket for an address of the form `unix:<PATH>`, IPv4 and IPv6 sockets on every
+/// interface for `*:<PORT>`, and a TCP socket otherwise, e.g. for `127.0.0.1:6969` or `[::1]:6969`.
+fn bind(addr: &str) -> io::Result<Server> {
+    #[cfg(unix)]
+    if let Some(path) = addr.strip_prefix("unix:") {
+        return Server::bind_unix(path);
+    }
+    if let Some(port) = addr.strip_prefix("*:") {
+        let port = port
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid port number"))?;
+        return Server::bind_dual_stack(port);
+    }
+    Server::bind(addr)
+}
 
-    // Match the request line to determine the response.
-    let (status_line, filename) = match &request_line[..] {
-        // Serve hello.html for root path
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "pages/hello.html"),
-        // Simulate a slow response for /sleep
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "pages/hello.html")
+/// Picks the address to listen on.
+///
+/// # Arguments
+/// * `env_addr` - The value of the `SERVER_ADDR` environment variable, if set.
+/// * `args` - The command-line arguments, without the program name.
+///
+/// `SERVER_ADDR` wins if it is set; failing that, `--addr <ADDR>` (or `--addr=<ADDR>`) is used;
+/// failing that, `DEFAULT_ADDR`. See `bind` for the forms the address can take.
+///
+/// # Errors
+/// Returns a message suitable for printing if an argument is unknown or `--addr` has no value.
+fn bind_address(env_addr: Option<String>, mut args: impl Iterator<Item = String>) -> Result<String, String> {
+    let mut arg_addr = None;
+    while let Some(arg) = args.next() {
+        if arg == "--addr" {
+            arg_addr = Some(args.next().ok_or("--addr needs a value, e.g. --addr 127.0.0.1:8080")?);
+        } else if let Some(value) = arg.strip_prefix("--addr=") {
+            arg_addr = Some(value.to_owned());
+        } else {
+            return Err(format!("unknown argument `{arg}`; usage: rust_webserver [--addr <ADDR>]"));
         }
-        // Serve 404.html for all other paths
-        _ => ("HTTP/1.1 404 NOT FOUND", "pages/404.html"),
-    };
+    }
 
-    // Read the contents of the HTML file to be served.
-    // Panics if the file does not exist or cannot be read.
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
+    Ok(env_addr.or(arg_addr).unwrap_or_else(|| DEFAULT_ADDR.to_owned()))
+}
 
-    // Format the HTTP response with status line, Content-Length header, and body.
-    // The response must be separated by CRLF (\r\n) as per HTTP protocol.
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+/// Builds the server's routing table.
+///
+/// - `GET /` serves `pages/hello.html` with 200 OK.
+/// - `GET /sleep` serves `pages/hello.html` with 200 OK after 5 seconds, without tying up a worker meanwhile.
+/// - `GET /healthz` answers `ok` for health checks, or 503 while shutting down (built into `Router`).
+/// - `GET /metrics` reports request and worker pool counters for Prometheus (built into `Router`).
+/// - Any other `GET` serves the matching file under `public/`, if there is one.
+/// - Any other request gets `pages/404.html` with 404 Not Found.
+fn routes() -> Router {
+    let mut router = Router::new();
+    // Serve hello.html for root path
+    router.add_route(Method::Get, "/", |_| hello());
+    // Simulate a slow response for /sleep; the timer sends it, so the worker is free in the meantime
+    router.add_route(Method::Get, "/sleep", |_| hello().delayed(Duration::from_secs(5)));
+    // Serve anything dropped into public/ by its path
+    router.serve_dir("public");
+    // Use our own page for unknown paths
+    router.error_page(404, ErrorPage::File("pages/404.html".into()));
+    router
+}
 
-    // Write the response to the TCP stream, sending it to the client.
-    // Panics if the write fails.
-    stream.write_all(response.as_bytes()).unwrap();
+/// Serves `pages/hello.html`, or 500 Internal Server Error if it can't be read.
+fn hello() -> Response {
+    Response::from_file_or_error(200, "pages/hello.html")
 }
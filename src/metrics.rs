@@ -0,0 +1,82 @@
+// Request and pool counters, exposed in the Prometheus text format on /metrics.
+use std::fmt::Write as _;                           // For building the exposition text
+use std::sync::atomic::{AtomicU64, Ordering};       // For counters updated by every worker
+use std::sync::{Arc, OnceLock};                     // For sharing the pool's bookkeeping
+
+use crate::{PoolState, ThreadPool};
+
+/// The status codes counted; a handler's out-of-range status still counts as a request.
+const STATUSES: std::ops::RangeInclusive<u16> = 100..=599;
+
+/// Counters for everything a [`Server`](crate::Server) answers, and the pool it runs on.
+pub(crate) struct Metrics {
+    requests: AtomicU64,              // Requests parsed and answered
+    statuses: Vec<AtomicU64>,         // Responses sent, indexed by status code from 100
+    pool: OnceLock<Arc<PoolState>>,   // The pool's job counts, once the server is running
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            requests: AtomicU64::new(0),
+            statuses: STATUSES.map(|_| AtomicU64::new(0)).collect(),
+            pool: OnceLock::new(),
+        }
+    }
+
+    /// Report job counts from `pool`. Only the first pool given is watched.
+    pub(crate) fn watch_pool(&self, pool: &ThreadPool) {
+        let _ = self.pool.set(Arc::clone(&pool.state));
+    }
+
+    /// Count a response with `status`, and the request it answered if one could be parsed.
+    pub(crate) fn record(&self, status: u16, parsed: bool) {
+        if parsed {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+        }
+        if STATUSES.contains(&status) {
+            self.statuses[usize::from(status - STATUSES.start())].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The counters in the Prometheus text exposition format (version 0.0.4).
+    ///
+    /// Every status class is listed, even at zero, but only the status codes that have been sent.
+    /// Pool gauges are left out until the server is running.
+    pub(crate) fn render(&self) -> String {
+        let mut text = String::new();
+        metric(&mut text, "http_requests_total", "counter", "Requests read and answered since the server started.");
+        let _ = writeln!(text, "http_requests_total {}", self.requests.load(Ordering::Relaxed));
+
+        let counts: Vec<(u16, u64)> = STATUSES
+            .zip(&self.statuses)
+            .map(|(status, count)| (status, count.load(Ordering::Relaxed)))
+            .collect();
+
+        metric(&mut text, "http_responses_total", "counter", "Responses sent, by status class.");
+        for class in 1..=5 {
+            let total: u64 = counts.iter().filter(|(status, _)| status / 100 == class).map(|(_, count)| count).sum();
+            let _ = writeln!(text, "http_responses_total{{class=\"{class}xx\"}} {total}");
+        }
+
+        metric(&mut text, "http_responses_by_status_total", "counter", "Responses sent, by status code.");
+        for (status, count) in counts.iter().filter(|(_, count)| *count > 0) {
+            let _ = writeln!(text, "http_responses_by_status_total{{code=\"{status}\"}} {count}");
+        }
+
+        if let Some(pool) = self.pool.get() {
+            let stats = pool.stats();
+            metric(&mut text, "pool_active_jobs", "gauge", "Jobs being run by a worker, including open connections.");
+            let _ = writeln!(text, "pool_active_jobs {}", stats.active_jobs);
+            metric(&mut text, "pool_queued_jobs", "gauge", "Jobs waiting in the queue for a free worker.");
+            let _ = writeln!(text, "pool_queued_jobs {}", stats.queued_jobs);
+        }
+        text
+    }
+}
+
+/// Write the `# HELP` and `# TYPE` lines that introduce metric `name`.
+fn metric(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {kind}");
+}
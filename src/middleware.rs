@@ -0,0 +1,38 @@
+// Logic that runs around every handler, such as timing or auth checks.
+use std::time::Instant; // For timing requests
+
+use crate::request::Request;
+use crate::response::Response;
+
+/// Code that wraps request handling; see [`Router::add_middleware`](crate::Router::add_middleware).
+///
+/// A middleware gets the request and `next`, which runs the rest of the chain (the remaining
+/// middleware, then the handler). It can change the request before passing it on, change the
+/// response on the way back, or answer by itself without calling `next` at all.
+///
+/// Closures with the same signature as [`Middleware::call`] are middleware too.
+pub trait Middleware: Send + Sync {
+    /// Handle `req`, calling `next` to run the rest of the chain.
+    fn call(&self, req: Request, next: &dyn Fn(Request) -> Response) -> Response;
+}
+
+impl<F> Middleware for F
+where F: Fn(Request, &dyn Fn(Request) -> Response) -> Response + Send + Sync, {
+    fn call(&self, req: Request, next: &dyn Fn(Request) -> Response) -> Response {
+        self(req, next)
+    }
+}
+
+/// Middleware that reports how long the rest of the chain took in an `X-Response-Time` header,
+/// in milliseconds, e.g. `X-Response-Time: 0.153ms`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timing;
+
+impl Middleware for Timing {
+    fn call(&self, req: Request, next: &dyn Fn(Request) -> Response) -> Response {
+        let started = Instant::now();
+        let response = next(req);
+        let millis = started.elapsed().as_secs_f64() * 1000.0;
+        response.set_header("X-Response-Time", &format!("{millis:.3}ms"))
+    }
+}
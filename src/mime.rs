@@ -0,0 +1,27 @@
+// Mapping from file extensions to MIME types for the Content-Type header.
+use std::path::Path; // For extracting the extension from a path
+
+/// Return the MIME type to send for a file at `path`, based on its extension.
+///
+/// Extensions are matched case-insensitively. Unknown or missing extensions map to
+/// `application/octet-stream`, which tells browsers to treat the body as opaque bytes.
+pub fn content_type_for(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
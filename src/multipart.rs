@@ -0,0 +1,178 @@
+// Parsing of multipart/form-data bodies, as sent by HTML forms that upload files.
+use std::collections::HashMap; // For part headers
+use std::{error, fmt};         // For displaying parse errors
+
+/// One field of a `multipart/form-data` body; see [`Request::multipart`](crate::Request::multipart).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// The part's headers, with lowercased names.
+    pub headers: HashMap<String, String>,
+    /// The form field's name, from `Content-Disposition`.
+    pub name: Option<String>,
+    /// For file uploads, the file's name on the client, from `Content-Disposition`.
+    pub filename: Option<String>,
+    /// The field's value or the file's contents, exactly as sent.
+    pub body: Vec<u8>,
+}
+
+impl Part {
+    /// Look up a header value by name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// The part's `Content-Type`, if it has one. Browsers send one with every file, and
+    /// none with plain text fields.
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+}
+
+/// Why a request body couldn't be read as multipart form data; see [`Request::multipart`](crate::Request::multipart).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultipartError {
+    /// The request's `Content-Type` isn't `multipart/form-data`; holds what it was, if anything.
+    WrongContentType(Option<String>),
+    /// The `Content-Type` has no `boundary` parameter.
+    MissingBoundary,
+    /// The body doesn't follow the multipart format; the message says what was wrong.
+    Malformed(&'static str),
+}
+
+impl MultipartError {
+    /// The status code to answer the client with: 415 for the wrong content type, 400 otherwise.
+    pub fn status(&self) -> u16 {
+        match self {
+            MultipartError::WrongContentType(_) => 415,
+            MultipartError::MissingBoundary | MultipartError::Malformed(_) => 400,
+        }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::WrongContentType(Some(found)) => {
+                write!(f, "expected Content-Type multipart/form-data, got {found}")
+            }
+            MultipartError::WrongContentType(None) => write!(f, "expected Content-Type multipart/form-data"),
+            MultipartError::MissingBoundary => write!(f, "multipart Content-Type has no boundary"),
+            MultipartError::Malformed(reason) => write!(f, "malformed multipart body: {reason}"),
+        }
+    }
+}
+
+impl error::Error for MultipartError {}
+
+/// Split `body` into its parts, each of which starts after a `--boundary` line.
+///
+/// Anything before the first boundary (the preamble) and after the closing `--boundary--`
+/// (the epilogue) is ignored. Each boundary must be at the start of a line; the line break
+/// before it belongs to the boundary, not to the part before.
+pub(crate) fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>, MultipartError> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+    let next_delimiter = [b"\r\n", delimiter].concat();
+
+    let mut pos = if body.starts_with(delimiter) {
+        delimiter.len()
+    } else {
+        let start = find(body, &next_delimiter).ok_or(MultipartError::Malformed("no boundary in body"))?;
+        start + next_delimiter.len()
+    };
+
+    let mut parts = Vec::new();
+    loop {
+        let rest = &body[pos..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        // A boundary line may have trailing spaces before its CRLF.
+        let padding = rest.iter().take_while(|&&byte| byte == b' ' || byte == b'\t').count();
+        let Some(rest) = rest[padding..].strip_prefix(b"\r\n") else {
+            return Err(MultipartError::Malformed("bad boundary line"));
+        };
+        let end = find(rest, &next_delimiter).ok_or(MultipartError::Malformed("part is never closed"))?;
+        parts.push(parse_part(&rest[..end])?);
+        pos = body.len() - rest.len() + end + next_delimiter.len();
+    }
+}
+
+/// Parse one part: header lines, a blank line, then the body.
+fn parse_part(part: &[u8]) -> Result<Part, MultipartError> {
+    // A part with no headers starts straight away with the blank line.
+    let (head, body) = match part.strip_prefix(b"\r\n") {
+        Some(body) => (&b""[..], body),
+        None => {
+            let split = find(part, b"\r\n\r\n")
+                .ok_or(MultipartError::Malformed("part has no blank line after its headers"))?;
+            (&part[..split], &part[split + 4..])
+        }
+    };
+    let head =
+        std::str::from_utf8(head).map_err(|_| MultipartError::Malformed("part headers are not valid UTF-8"))?;
+
+    let mut headers = HashMap::new();
+    for line in head.split("\r\n").filter(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or(MultipartError::Malformed("bad part header line"))?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+    }
+
+    let disposition = headers.get("content-disposition").map_or("", String::as_str);
+    Ok(Part {
+        name: param(disposition, "name"),
+        filename: param(disposition, "filename"),
+        body: body.to_vec(),
+        headers,
+    })
+}
+
+/// The value of parameter `name` in a header value such as
+/// `form-data; name="upload"; filename="a.txt"`, unquoted.
+///
+/// Parameter names are matched ignoring case. Inside quotes, `;` doesn't end the value
+/// and a backslash escapes the next character.
+pub(crate) fn param(value: &str, name: &str) -> Option<String> {
+    // Skip the value itself, such as `form-data`, up to the first `;`.
+    let mut rest = value.split_once(';')?.1;
+    loop {
+        let (key, after) = rest.split_once('=')?;
+        // A parameter without a value, such as `; foo; name=x`, is skipped.
+        let key = key.rsplit(';').next().unwrap_or(key);
+        let after = after.trim_start();
+
+        let (param_value, remainder) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut unquoted = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (i, '"') => break i + 1,
+                        (_, '\\') => unquoted.push(chars.next()?.1),
+                        (_, c) => unquoted.push(c),
+                    }
+                };
+                let remainder = quoted[end..].split_once(';').map_or("", |(_, remainder)| remainder);
+                (unquoted, remainder)
+            }
+            None => match after.split_once(';') {
+                Some((token, remainder)) => (token.trim().to_owned(), remainder),
+                None => (after.trim().to_owned(), ""),
+            },
+        };
+
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(param_value);
+        }
+        if remainder.is_empty() {
+            return None;
+        }
+        rest = remainder;
+    }
+}
+
+/// The index of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
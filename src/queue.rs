@@ -0,0 +1,395 @@
+// Shared queue that the pool pushes jobs into and workers pull jobs from.
+use std::cmp::Ordering;                        // For ordering jobs by priority
+use std::collections::BinaryHeap;              // Max-heap of waiting jobs
+use std::iter;                                 // For retrying steals
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, RwLock}; // Lock and signalling around the heap, registry of stealers
+use std::sync::atomic::{self, AtomicBool, AtomicUsize}; // Per-worker stop flags, job count for stealing mode
+use std::time::{Duration, Instant};            // For idle timeouts
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker as Deque}; // Deques for work-stealing mode
+
+use super::Job;
+
+/// A job queue shared by the pool and its workers.
+///
+/// By default jobs wait in a single heap that hands out higher-priority jobs first,
+/// and jobs of equal priority in the order they were pushed. In work-stealing mode
+/// jobs go into a global injector instead; each worker moves batches of them into its
+/// own deque and, when that runs dry, steals from the other workers' deques.
+/// Priorities are ignored in that mode.
+///
+/// The queue can optionally be bounded, in which case pushing waits for room.
+pub(crate) struct JobQueue {
+    inner: Mutex<Inner>,        // The heap plus its bookkeeping
+    available: Condvar,         // Signalled when a job is pushed, the queue closes, or a worker is stopped
+    space: Condvar,             // Signalled when a job is taken off the queue
+    capacity: Option<usize>,    // Maximum number of waiting jobs (None = unbounded)
+    stealing: Option<Stealing>, // Set in work-stealing mode, where the heap goes unused
+}
+
+/// The shared half of work-stealing mode.
+struct Stealing {
+    injector: Injector<Job>,                   // Where new jobs are pushed
+    stealers: RwLock<Vec<(usize, Stealer<Job>)>>, // Handles onto each worker's deque, by worker id
+    waiting: AtomicUsize,                      // Jobs not yet handed to a worker, wherever they are
+}
+
+/// An idle worker's wait ran past its deadline.
+struct TimedOut;
+
+/// A worker's own end of the queue, created by [`JobQueue::local`].
+pub(crate) struct LocalQueue {
+    id: usize,                // The worker's id, to find its stealer again
+    deque: Option<Deque<Job>>, // The worker's deque in work-stealing mode
+}
+
+struct Inner {
+    heap: BinaryHeap<PrioritizedJob>, // Jobs waiting for a worker
+    next_seq: u64,                    // Increasing sequence number, used to keep FIFO order within a priority
+    idle_workers: usize,              // Workers blocked in `pop`, ready to take a job straight away
+    closed: bool,                     // Set once the pool shuts down
+}
+
+/// What a worker got from [`JobQueue::pop`].
+pub(crate) enum Pop {
+    /// A job to run.
+    Job(Job),
+    /// No job arrived within the idle timeout.
+    Idle,
+    /// The worker was stopped, or the queue is closed and drained.
+    Done,
+}
+
+/// Why a job could not be pushed without waiting.
+pub(crate) enum PushError {
+    /// The bounded queue has no room.
+    Full(Job),
+    /// The queue has been closed.
+    Closed(Job),
+}
+
+/// A job together with the ordering information used by the heap.
+struct PrioritizedJob {
+    priority: u8,
+    seq: u64,
+    job: Job,
+}
+
+impl PartialEq for PrioritizedJob {
+    fn eq(&self, other: &PrioritizedJob) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PrioritizedJob {}
+
+impl PartialOrd for PrioritizedJob {
+    fn partial_cmp(&self, other: &PrioritizedJob) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PrioritizedJob {
+    fn cmp(&self, other: &PrioritizedJob) -> Ordering {
+        // Highest priority first; within a priority, the lowest sequence number (oldest) first.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl JobQueue {
+    /// Create an empty queue, bounded to `capacity` waiting jobs if given,
+    /// in work-stealing mode if `work_stealing` is set.
+    ///
+    /// A capacity of zero only accepts a job when an idle worker is ready to take it.
+    pub(crate) fn new(capacity: Option<usize>, work_stealing: bool) -> JobQueue {
+        JobQueue {
+            inner: Mutex::new(Inner {
+                heap: BinaryHeap::new(),
+                next_seq: 0,
+                idle_workers: 0,
+                closed: false,
+            }),
+            available: Condvar::new(),
+            space: Condvar::new(),
+            capacity,
+            stealing: work_stealing.then(|| Stealing {
+                injector: Injector::new(),
+                stealers: RwLock::new(Vec::new()),
+                waiting: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Create the local end of the queue for worker `id`, which must pass it to every `pop`.
+    ///
+    /// Call this on the worker's own thread; in work-stealing mode it registers the
+    /// worker's deque so the others can steal from it.
+    pub(crate) fn local(&self, id: usize) -> LocalQueue {
+        let deque = self.stealing.as_ref().map(|stealing| {
+            let deque = Deque::new_fifo();
+            stealing.stealers.write().unwrap_or_else(PoisonError::into_inner).push((id, deque.stealer()));
+            deque
+        });
+        LocalQueue { id, deque }
+    }
+
+    /// Push a job, waiting for room if the queue is bounded and full.
+    /// Returns whether a worker was waiting to take it.
+    ///
+    /// Hands the job back if the queue has been closed.
+    pub(crate) fn push(&self, priority: u8, job: Job) -> Result<bool, Job> {
+        let inner = self.lock();
+        let mut inner = self
+            .space
+            .wait_while(inner, |inner| !inner.closed && !self.has_room(inner))
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if inner.closed {
+            return Err(job);
+        }
+
+        Ok(self.insert(&mut inner, priority, job))
+    }
+
+    /// Push a job without waiting. Returns whether a worker was waiting to take it.
+    pub(crate) fn try_push(&self, priority: u8, job: Job) -> Result<bool, PushError> {
+        let mut inner = self.lock();
+
+        if inner.closed {
+            return Err(PushError::Closed(job));
+        }
+        if !self.has_room(&inner) {
+            return Err(PushError::Full(job));
+        }
+
+        Ok(self.insert(&mut inner, priority, job))
+    }
+
+    /// Take the next job, waiting until one is available or `idle_timeout` (if any) passes.
+    ///
+    /// Returns [`Pop::Done`] once `stop` is set, or once the queue is closed and every
+    /// remaining job has been handed out.
+    pub(crate) fn pop(&self, stop: &AtomicBool, local: &LocalQueue, idle_timeout: Option<Duration>) -> Pop {
+        let deadline = idle_timeout.map(|timeout| Instant::now() + timeout);
+        let found = match (&self.stealing, &local.deque) {
+            (Some(stealing), Some(deque)) => self.pop_stealing(stealing, stop, local.id, deque, deadline),
+            _ => self.pop_heap(stop, deadline),
+        };
+        match found {
+            Ok(Some(job)) => Pop::Job(job),
+            Ok(None) => Pop::Done,
+            Err(TimedOut) => Pop::Idle,
+        }
+    }
+
+    /// The number of jobs not yet handed to a worker.
+    pub(crate) fn pending(&self) -> usize {
+        let inner = self.lock();
+        match &self.stealing {
+            Some(stealing) => stealing.waiting.load(atomic::Ordering::SeqCst),
+            None => inner.heap.len(),
+        }
+    }
+
+    /// `pop` for the default mode: take the highest-priority job from the heap.
+    fn pop_heap(&self, stop: &AtomicBool, deadline: Option<Instant>) -> Result<Option<Job>, TimedOut> {
+        let mut inner = self.lock();
+        let mut timed_out = false;
+
+        loop {
+            // Checked under the lock, so a stop signal sent by `wake_workers` can't be missed.
+            if stop.load(atomic::Ordering::SeqCst) {
+                // A push may have woken this worker instead of one that will run the job.
+                if !inner.heap.is_empty() {
+                    self.available.notify_one();
+                }
+                return Ok(None);
+            }
+
+            if let Some(entry) = inner.heap.pop() {
+                self.space.notify_one();
+                return Ok(Some(entry.job));
+            }
+
+            if inner.closed {
+                return Ok(None);
+            }
+            if timed_out {
+                return Err(TimedOut);
+            }
+
+            // An idle worker makes room in a bounded queue, so let a waiting pusher know.
+            inner.idle_workers += 1;
+            if self.capacity.is_some() {
+                self.space.notify_one();
+            }
+            (inner, timed_out) = self.wait(inner, deadline);
+        }
+    }
+
+    /// `pop` for work-stealing mode: take a job from the worker's own deque, the injector,
+    /// or another worker's deque, in that order.
+    ///
+    /// Finding a job doesn't take the lock; it's only needed to sleep without missing a push.
+    fn pop_stealing(
+        &self,
+        stealing: &Stealing,
+        stop: &AtomicBool,
+        id: usize,
+        deque: &Deque<Job>,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Job>, TimedOut> {
+        let mut timed_out = false;
+
+        loop {
+            if !stop.load(atomic::Ordering::SeqCst)
+                && let Some(job) = stealing.find_job(deque)
+            {
+                self.taken(stealing);
+                return Ok(Some(job));
+            }
+
+            let mut inner = self.lock();
+
+            // Checked under the lock, so a stop signal sent by `wake_workers` can't be missed.
+            if stop.load(atomic::Ordering::SeqCst) {
+                // Hand back anything this worker had claimed, for the others to run.
+                stealing.stealers.write().unwrap_or_else(PoisonError::into_inner).retain(|(owner, _)| *owner != id);
+                while let Some(job) = deque.pop() {
+                    stealing.injector.push(job);
+                }
+                if stealing.waiting.load(atomic::Ordering::SeqCst) > 0 {
+                    self.available.notify_one();
+                }
+                return Ok(None);
+            }
+
+            // Look again under the lock: a push that happened after the search above
+            // notified before this worker started waiting, so the wake-up would be lost.
+            if let Some(job) = stealing.find_job(deque) {
+                drop(inner);
+                self.taken(stealing);
+                return Ok(Some(job));
+            }
+
+            if inner.closed {
+                return Ok(None);
+            }
+            if timed_out {
+                return Err(TimedOut);
+            }
+
+            inner.idle_workers += 1;
+            if self.capacity.is_some() {
+                self.space.notify_one();
+            }
+            timed_out = self.wait(inner, deadline).1;
+        }
+    }
+
+    /// Wait on `available` as an idle worker, until notified or `deadline` passes.
+    ///
+    /// Also returns whether the deadline has passed. Callers look for a job once more
+    /// before giving up, so a push that raced with the timeout isn't left unclaimed.
+    fn wait<'a>(&self, inner: MutexGuard<'a, Inner>, deadline: Option<Instant>) -> (MutexGuard<'a, Inner>, bool) {
+        let mut inner = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                self.available.wait_timeout(inner, timeout).unwrap_or_else(PoisonError::into_inner).0
+            }
+            None => self.available.wait(inner).unwrap_or_else(PoisonError::into_inner),
+        };
+        inner.idle_workers -= 1;
+
+        let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        (inner, timed_out)
+    }
+
+    /// Record that a worker took a job in work-stealing mode, making room in a bounded queue.
+    fn taken(&self, stealing: &Stealing) {
+        stealing.waiting.fetch_sub(1, atomic::Ordering::SeqCst);
+        if self.capacity.is_some() {
+            // Notify under the lock so a pusher that just found the queue full can't miss it.
+            let _inner = self.lock();
+            self.space.notify_one();
+        }
+    }
+
+    /// Close the queue: pushes fail from now on, and workers exit once it is drained.
+    pub(crate) fn close(&self) {
+        self.lock().closed = true;
+        self.available.notify_all();
+        self.space.notify_all();
+    }
+
+    /// Whether `close` has been called.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Wake every waiting worker so they re-check their stop flags.
+    pub(crate) fn wake_workers(&self) {
+        // Taking the lock orders this wake-up after any stop flag that was just set.
+        let _inner = self.lock();
+        self.available.notify_all();
+    }
+
+    /// Lock the heap and its bookkeeping.
+    ///
+    /// A thread that panicked while holding the lock leaves it poisoned, but jobs never run
+    /// under it, so what it guards is still consistent: the other workers carry on with it
+    /// rather than going down too.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn has_room(&self, inner: &Inner) -> bool {
+        let waiting = match &self.stealing {
+            Some(stealing) => stealing.waiting.load(atomic::Ordering::SeqCst),
+            None => inner.heap.len(),
+        };
+        match self.capacity {
+            // Jobs an idle worker is about to take don't count as waiting.
+            Some(cap) => waiting < cap + inner.idle_workers,
+            None => true,
+        }
+    }
+
+    /// Add a job and wake a worker for it. Returns whether one was waiting.
+    fn insert(&self, inner: &mut Inner, priority: u8, job: Job) -> bool {
+        match &self.stealing {
+            Some(stealing) => {
+                stealing.waiting.fetch_add(1, atomic::Ordering::SeqCst);
+                stealing.injector.push(job);
+            }
+            None => {
+                let seq = inner.next_seq;
+                inner.next_seq += 1;
+                inner.heap.push(PrioritizedJob { priority, seq, job });
+            }
+        }
+        self.available.notify_one();
+        inner.idle_workers > 0
+    }
+}
+
+impl Stealing {
+    /// Find a job for the worker owning `deque`: its own jobs first, then a batch from the
+    /// injector, then one stolen from another worker.
+    fn find_job(&self, deque: &Deque<Job>) -> Option<Job> {
+        deque.pop().or_else(|| {
+            // A steal can fail spuriously when racing another thief; try again until
+            // every source has given a definite answer.
+            iter::repeat_with(|| {
+                self.injector.steal_batch_and_pop(deque).or_else(|| {
+                    let stealers = self.stealers.read().unwrap_or_else(PoisonError::into_inner);
+                    stealers.iter().map(|(_, stealer)| stealer.steal()).collect::<Steal<Job>>()
+                })
+            })
+            .find(|steal| !steal.is_retry())
+            .and_then(Steal::success)
+        })
+    }
+}
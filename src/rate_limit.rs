@@ -0,0 +1,100 @@
+// Capping how many requests each client IP may make in a time window.
+use std::collections::HashMap;      // For one window per client
+use std::net::IpAddr;               // For keying windows by client address
+use std::sync::Mutex;               // For sharing the windows between workers
+use std::time::{Duration, Instant}; // For measuring windows
+
+use crate::middleware::Middleware;
+use crate::request::Request;
+use crate::response::Response;
+
+/// Middleware that lets each client IP make at most a fixed number of requests per window.
+///
+/// Windows are fixed: a client's first request starts its window, and once `requests` have been
+/// made in it, the rest get `429 Too Many Requests` until it ends, with a `Retry-After` header
+/// saying how many seconds that is. Requests whose client address is unknown are not limited.
+///
+/// Clients whose windows have ended are forgotten about once per window, so memory stays
+/// proportional to the number of clients seen recently.
+#[derive(Debug)]
+pub struct RateLimit {
+    requests: u32,           // Requests allowed per window
+    window: Duration,        // Length of each client's window
+    clients: Mutex<Clients>, // Windows by client address
+}
+
+/// The windows tracked by a [`RateLimit`].
+#[derive(Debug)]
+struct Clients {
+    windows: HashMap<IpAddr, Window>, // The current window of each recent client
+    last_prune: Instant,              // When ended windows were last removed
+}
+
+/// One client's current window.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant, // When the window began
+    count: u32,       // Requests made in it so far
+}
+
+impl RateLimit {
+    /// Allow each client IP `requests` requests per `window`.
+    ///
+    /// # Panics
+    /// Panics if `window` is zero.
+    pub fn new(requests: u32, window: Duration) -> RateLimit {
+        assert!(!window.is_zero(), "rate limit window must be non-zero");
+        RateLimit {
+            requests,
+            window,
+            clients: Mutex::new(Clients {
+                windows: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /// Count a request from `ip`. Returns how long until the client may try again
+    /// if it is over the limit.
+    fn check(&self, ip: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+
+        // Drop clients whose windows have ended, at most once per window.
+        if now.duration_since(clients.last_prune) >= self.window {
+            clients
+                .windows
+                .retain(|_, window| now.duration_since(window.started) < self.window);
+            clients.last_prune = now;
+        }
+
+        let window = clients.windows.entry(ip).or_insert(Window { started: now, count: 0 });
+        if now.duration_since(window.started) >= self.window {
+            *window = Window { started: now, count: 0 };
+        }
+
+        if window.count < self.requests {
+            window.count += 1;
+            None
+        } else {
+            Some(self.window - now.duration_since(window.started))
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn call(&self, req: Request, next: &dyn Fn(Request) -> Response) -> Response {
+        let Some(ip) = req.client else {
+            return next(req);
+        };
+
+        match self.check(ip) {
+            None => next(req),
+            Some(wait) => {
+                // Retry-After is in whole seconds; round up so a client that waits that long gets in.
+                let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                Response::with_status(429).header("Retry-After", &secs.to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,656 @@
+// Parsing of incoming HTTP requests.
+use std::collections::HashMap; // For the header map
+use std::{error, fmt};          // For displaying methods and parse errors
+use std::io::{self, BufRead};  // For reading the request line by line
+use std::net::IpAddr;          // For the client's address
+
+use crate::multipart::{self, MultipartError, Part};
+use crate::url;
+
+/// An HTTP request method.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    /// Any other method, such as `PROPFIND` or `TRACE`, exactly as the client sent it.
+    Other(String),
+}
+
+impl Method {
+    /// Parse a method token such as `GET`. Method names are case-sensitive, so `get` is
+    /// [`Method::Other`], like every method without a variant of its own.
+    pub fn parse(token: &str) -> Method {
+        match token {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "OPTIONS" => Method::Options,
+            other => Method::Other(other.to_owned()),
+        }
+    }
+
+    /// The method's name as it appears on the request line.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Options => "OPTIONS",
+            Method::Other(name) => name,
+        }
+    }
+
+    /// Whether this is `GET` or `HEAD`, the methods that read a resource without changing it.
+    pub(crate) fn is_get_or_head(&self) -> bool {
+        matches!(self, Method::Get | Method::Head)
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An HTTP request read from a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The request method, e.g. [`Method::Get`].
+    pub method: Method,
+    /// The request path without the query string, percent-decoded, e.g. `/search` or `/my file.txt`.
+    pub path: String,
+    /// Query string parameters, percent-decoded; see [`Request::query_param`].
+    pub query: HashMap<String, String>,
+    /// The protocol version, e.g. `HTTP/1.1`.
+    pub version: String,
+    /// Request headers keyed by lower-cased name, since header names are case-insensitive.
+    pub headers: HashMap<String, String>,
+    /// The request body, read according to the `Content-Length` header (empty if absent).
+    pub body: Vec<u8>,
+    /// The address of the client that sent the request, if known.
+    /// The server fills this in; requests read with [`Request::from_reader`] start without one.
+    pub client: Option<IpAddr>,
+    /// The id that ties this request's log lines together: the client's `X-Request-Id`, or one
+    /// the server made up. The server fills this in and sends it back in the response's
+    /// `X-Request-Id`; requests read with [`Request::from_reader`] start without one.
+    pub id: Option<String>,
+    /// What the matched route's `:name` and `*name` segments matched, by name; see
+    /// [`Router::add_route`](crate::Router::add_route). Empty for routes without any.
+    pub params: HashMap<String, String>,
+}
+
+/// Limits applied while reading a request, protecting the server from oversized input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// The largest body, in bytes, that will be read. Larger requests are rejected with 413.
+    pub max_body_size: usize,
+    /// The most bytes the request line and headers may take up, line endings included.
+    /// Longer heads are rejected with 431.
+    pub max_header_size: usize,
+    /// The most header lines a request may have. Requests with more are rejected with 431.
+    pub max_headers: usize,
+}
+
+impl Default for RequestLimits {
+    /// 1 MiB of body, and 16 KiB and 100 lines of headers.
+    fn default() -> RequestLimits {
+        RequestLimits { max_body_size: 1024 * 1024, max_header_size: 16 * 1024, max_headers: 100 }
+    }
+}
+
+/// The longest chunk size line accepted in a chunked body, extensions included.
+const MAX_CHUNK_LINE: usize = 1024;
+
+/// How the length of a request body is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BodyLength {
+    /// `Content-Length` bytes long, or empty without that header.
+    Fixed(usize),
+    /// Sent in chunks with `Transfer-Encoding: chunked`.
+    Chunked,
+}
+
+/// Why a request could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading from the client failed, or the client disconnected mid-request.
+    Io(io::Error),
+    /// The request is not valid HTTP; the message says what was wrong.
+    Malformed(&'static str),
+    /// The declared body is larger than [`RequestLimits::max_body_size`].
+    PayloadTooLarge,
+    /// The request line and headers are longer than [`RequestLimits::max_header_size`],
+    /// or there are more headers than [`RequestLimits::max_headers`].
+    HeadersTooLarge,
+    /// The request is for an HTTP version other than 1.0 and 1.1, such as `HTTP/2.0` sent in
+    /// cleartext or `HTTP/0.9`.
+    UnsupportedVersion,
+}
+
+impl ParseError {
+    /// The status code to answer the client with.
+    ///
+    /// A read that timed out (see [`ParseError::is_timeout`]) gets 408, other errors 400, 413, 431 or 505.
+    pub fn status(&self) -> u16 {
+        match self {
+            _ if self.is_timeout() => 408,
+            ParseError::Io(_) | ParseError::Malformed(_) => 400,
+            ParseError::PayloadTooLarge => 413,
+            ParseError::HeadersTooLarge => 431,
+            ParseError::UnsupportedVersion => 505,
+        }
+    }
+
+    /// Whether the client was too slow to send the request within the socket's read timeout.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ParseError::Io(e) if is_timeout(e))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "failed to read request: {e}"),
+            ParseError::Malformed(reason) => write!(f, "malformed request: {reason}"),
+            ParseError::PayloadTooLarge => write!(f, "request body is too large"),
+            ParseError::HeadersTooLarge => write!(f, "request headers are too large"),
+            ParseError::UnsupportedVersion => write!(f, "unsupported HTTP version"),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            ParseError::Malformed(_)
+            | ParseError::PayloadTooLarge
+            | ParseError::HeadersTooLarge
+            | ParseError::UnsupportedVersion => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+/// Why a request body couldn't be read as a form; see [`Request::form`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormError {
+    /// The request's `Content-Type` isn't `application/x-www-form-urlencoded`; holds what it was, if anything.
+    WrongContentType(Option<String>),
+    /// The body isn't valid UTF-8.
+    InvalidEncoding,
+}
+
+impl FormError {
+    /// The status code to answer the client with: 415 for the wrong content type, 400 otherwise.
+    pub fn status(&self) -> u16 {
+        match self {
+            FormError::WrongContentType(_) => 415,
+            FormError::InvalidEncoding => 400,
+        }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormError::WrongContentType(Some(found)) => {
+                write!(f, "expected Content-Type application/x-www-form-urlencoded, got {found}")
+            }
+            FormError::WrongContentType(None) => {
+                write!(f, "expected Content-Type application/x-www-form-urlencoded")
+            }
+            FormError::InvalidEncoding => write!(f, "form body is not valid UTF-8"),
+        }
+    }
+}
+
+impl error::Error for FormError {}
+
+/// Why a request body couldn't be read as JSON; see [`Request::json`].
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub enum JsonError {
+    /// The request's `Content-Type` isn't `application/json`; holds what it was, if anything.
+    WrongContentType(Option<String>),
+    /// The body isn't valid JSON, or doesn't match the expected type.
+    Invalid(serde_json::Error),
+}
+
+#[cfg(feature = "json")]
+impl JsonError {
+    /// The status code to answer the client with: 415 for the wrong content type, 400 otherwise.
+    pub fn status(&self) -> u16 {
+        match self {
+            JsonError::WrongContentType(_) => 415,
+            JsonError::Invalid(_) => 400,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::WrongContentType(Some(found)) => {
+                write!(f, "expected Content-Type application/json, got {found}")
+            }
+            JsonError::WrongContentType(None) => write!(f, "expected Content-Type application/json"),
+            JsonError::Invalid(e) => write!(f, "invalid JSON body: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            JsonError::WrongContentType(_) => None,
+            JsonError::Invalid(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> JsonError {
+        JsonError::Invalid(e)
+    }
+}
+
+impl Request {
+    /// Read a request line, its headers, and its body from `reader`, using the default limits.
+    ///
+    /// See [`Request::from_reader_with_limits`].
+    pub fn from_reader<R: BufRead>(reader: &mut R) -> Result<Request, ParseError> {
+        Request::from_reader_with_limits(reader, &RequestLimits::default())
+    }
+
+    /// Read a request line, its headers, and its body from `reader`.
+    ///
+    /// The body is exactly `Content-Length` bytes long, decoded from chunks with
+    /// `Transfer-Encoding: chunked`, or empty without either header.
+    /// Anything after the body is left unread in `reader`. The path is percent-decoded.
+    ///
+    /// # Errors
+    /// Returns [`ParseError::Io`] if reading fails or the stream ends before the request
+    /// is complete, [`ParseError::Malformed`] if the request line, a header or the chunked
+    /// encoding of the body is invalid, the request has both `Content-Length` and
+    /// `Transfer-Encoding` or two different `Content-Length`s (which a proxy in front could
+    /// read differently, letting a second request be smuggled past it), or the path isn't valid
+    /// percent-encoded UTF-8 or decodes to a control character, [`ParseError::UnsupportedVersion`]
+    /// for versions other than HTTP/1.0 and 1.1, [`ParseError::PayloadTooLarge`] if the body would exceed
+    /// `limits.max_body_size`, or [`ParseError::HeadersTooLarge`] if the headers exceed
+    /// `limits.max_header_size` or `limits.max_headers`. No more than the limits allow is read
+    /// to find out.
+    pub fn from_reader_with_limits<R: BufRead>(
+        reader: &mut R,
+        limits: &RequestLimits,
+    ) -> Result<Request, ParseError> {
+        let mut request = Request::read_head(reader, limits)?;
+        request.read_body(reader, limits)?;
+        Ok(request)
+    }
+
+    /// Read a request line and its headers from `reader`, leaving the body unread.
+    pub(crate) fn read_head<R: BufRead>(reader: &mut R, limits: &RequestLimits) -> Result<Request, ParseError> {
+        // Every line read counts against the header budget.
+        let mut budget = limits.max_header_size;
+
+        // The request line looks like "GET /index.html HTTP/1.1".
+        let request_line = read_line(reader, &mut budget)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(path), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseError::Malformed("bad request line"));
+        };
+
+        // The version looks like "HTTP/1.1", with one digit either side of the dot. Anything
+        // else is garbage; a well-formed version the server doesn't speak gets 505 instead.
+        let is_digit = |number: &str| number.len() == 1 && number.as_bytes()[0].is_ascii_digit();
+        let numbers = version.strip_prefix("HTTP/").and_then(|numbers| numbers.split_once('.'));
+        let Some(numbers) = numbers.filter(|&(major, minor)| is_digit(major) && is_digit(minor)) else {
+            return Err(ParseError::Malformed("bad HTTP version"));
+        };
+        if !matches!(numbers, ("1", "0" | "1")) {
+            return Err(ParseError::UnsupportedVersion);
+        }
+
+        // Split "/search?q=rust" into the path and the query string.
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+
+        // Decode "/my%20file.txt" to "/my file.txt". A decoded control character, such as a
+        // newline, could split a log line or smuggle a NUL into a file name, so it's refused.
+        let path = url::percent_decode(path, false).ok_or(ParseError::Malformed("bad percent-encoding in path"))?;
+        if path.chars().any(char::is_control) {
+            return Err(ParseError::Malformed("control character in path"));
+        }
+
+        let mut request = Request {
+            method: Method::parse(method),
+            path,
+            query: url::parse_query(query),
+            version: version.to_owned(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            client: None,
+            id: None,
+            params: HashMap::new(),
+        };
+
+        // Each header line looks like "Name: value"; an empty line ends the headers.
+        let mut count = 0;
+        loop {
+            let line = read_line(reader, &mut budget)?;
+            if line.is_empty() {
+                break;
+            }
+            count += 1;
+            if count > limits.max_headers {
+                return Err(ParseError::HeadersTooLarge);
+            }
+
+            let (name, value) = line
+                .split_once(':')
+                .ok_or(ParseError::Malformed("bad header line"))?;
+            // Whitespace before the colon (or a line folded onto the one before) could make a proxy
+            // miss a `Transfer-Encoding : chunked` that would otherwise count here, so it is refused.
+            if name.is_empty() || name.bytes().any(|b| b.is_ascii_whitespace()) {
+                return Err(ParseError::Malformed("bad header name"));
+            }
+            let (name, value) = (name.to_ascii_lowercase(), value.trim());
+            // A later header replaces an earlier one of the same name, except that two different
+            // Content-Lengths are refused: a proxy in front may have gone by the other one, and
+            // then the two disagree on where this request ends and the next one starts.
+            if name == "content-length" && request.headers.get(&name).is_some_and(|earlier| earlier != value) {
+                return Err(ParseError::Malformed("conflicting Content-Length headers"));
+            }
+            request.headers.insert(name, value.to_owned());
+        }
+
+        Ok(request)
+    }
+
+    /// Read the body following the headers: exactly `Content-Length` bytes, or the chunks of a
+    /// `Transfer-Encoding: chunked` body put back together.
+    pub(crate) fn read_body<R: BufRead>(&mut self, reader: &mut R, limits: &RequestLimits) -> Result<(), ParseError> {
+        match self.body_length(limits)? {
+            BodyLength::Fixed(0) => {}
+            // Sizes over the limit were refused before allocating anything for them.
+            BodyLength::Fixed(length) => {
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body)?;
+                self.body = body;
+            }
+            BodyLength::Chunked => self.body = read_chunked(reader, limits)?,
+        }
+        Ok(())
+    }
+
+    /// How long the body the headers announce is: `Content-Length`, chunked, or zero without either.
+    ///
+    /// # Errors
+    /// Returns [`ParseError::Malformed`] if `Content-Length` isn't plain decimal digits (so lists
+    /// such as `5, 6` and signs such as `+5` are refused), the request has both headers, or its `Transfer-Encoding` is anything but
+    /// `chunked`. A server and a proxy in front of it that read such a request differently can
+    /// disagree on where its body ends, letting the rest be smuggled through as a second request
+    /// the proxy never checked; refusing it outright is the only answer both agree on. Returns [`ParseError::PayloadTooLarge`] if
+    /// `Content-Length` exceeds `limits.max_body_size`.
+    pub(crate) fn body_length(&self, limits: &RequestLimits) -> Result<BodyLength, ParseError> {
+        match (self.header("Transfer-Encoding"), self.header("Content-Length")) {
+            (None, None) => Ok(BodyLength::Fixed(0)),
+            (Some(_), Some(_)) => Err(ParseError::Malformed("both Transfer-Encoding and Content-Length")),
+            (Some(coding), None) if coding.eq_ignore_ascii_case("chunked") => Ok(BodyLength::Chunked),
+            (Some(_), None) => Err(ParseError::Malformed("unsupported Transfer-Encoding")),
+            (None, Some(length)) => {
+                // `parse` would also take a leading `+`, which other servers and proxies may not.
+                let digits = !length.is_empty() && length.bytes().all(|b| b.is_ascii_digit());
+                let length: usize = length
+                    .parse()
+                    .ok()
+                    .filter(|_| digits)
+                    .ok_or(ParseError::Malformed("bad Content-Length header"))?;
+                if length > limits.max_body_size {
+                    return Err(ParseError::PayloadTooLarge);
+                }
+                Ok(BodyLength::Fixed(length))
+            }
+        }
+    }
+
+    /// Whether the client is waiting for `100 Continue` before sending the body
+    /// (`Expect: 100-continue`). HTTP/1.0 clients don't know the interim response, so never are.
+    pub(crate) fn expects_continue(&self) -> bool {
+        !self.is_http_1_0()
+            && self.header("Expect").is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    }
+
+    /// Look up a query string parameter by name.
+    ///
+    /// For `?a=1&b=&c`, `a` is `"1"` and both `b` and `c` are `""`.
+    /// Keys and values are percent-decoded, with `+` decoding to a space.
+    /// If a key appears more than once, the first value wins.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+
+    /// Look up what the route's `:name` or `*name` segment matched, e.g. `id` for `/users/:id`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+
+    /// Look up a header value by name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// Parse the `Cookie` header into a map from cookie name to value.
+    ///
+    /// `Cookie: a=1; b=two=2` gives `a` → `"1"` and `b` → `"two=2"`: pairs are split at the first
+    /// `=`, and names and values are trimmed. Values are returned as sent, without decoding, and
+    /// when a name repeats the first value wins. Pairs without an `=` or a name are skipped, and a request
+    /// without a `Cookie` header gives an empty map.
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        for pair in self.header("Cookie").unwrap_or("").split(';') {
+            if let Some((name, value)) = pair.split_once('=')
+                && !name.trim().is_empty()
+            {
+                cookies
+                    .entry(name.trim().to_owned())
+                    .or_insert_with(|| value.trim().to_owned());
+            }
+        }
+        cookies
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body, as sent by HTML forms.
+    ///
+    /// The body is decoded like a query string (see [`Request::query_param`]): `a=1&b=2` gives
+    /// two entries, `+` decodes to a space, a key without a value maps to `""`, and when a key
+    /// repeats the first value wins.
+    ///
+    /// # Errors
+    /// Returns [`FormError::WrongContentType`] unless the `Content-Type` is
+    /// `application/x-www-form-urlencoded` (parameters are ignored), or
+    /// [`FormError::InvalidEncoding`] if the body isn't UTF-8.
+    pub fn form(&self) -> Result<HashMap<String, String>, FormError> {
+        let content_type = self.header("Content-Type");
+        if !content_type.is_some_and(|value| has_media_type(value, "application/x-www-form-urlencoded")) {
+            return Err(FormError::WrongContentType(content_type.map(str::to_owned)));
+        }
+
+        let body = std::str::from_utf8(&self.body).map_err(|_| FormError::InvalidEncoding)?;
+        Ok(url::parse_query(body))
+    }
+
+    /// Parse a `multipart/form-data` body, as sent by HTML forms with file uploads
+    /// (`<form enctype="multipart/form-data">`), into its parts, in order.
+    ///
+    /// Each part has its own headers, and the field `name` and, for files, the `filename` from
+    /// its `Content-Disposition`. Bodies are returned as sent: text fields aren't decoded.
+    /// The whole body is read before the handler runs, so uploads bigger than the server's
+    /// [`Server::max_body_size`](crate::Server::max_body_size) are turned away with 413.
+    ///
+    /// # Errors
+    /// Returns [`MultipartError::WrongContentType`] unless the `Content-Type` is
+    /// `multipart/form-data`, [`MultipartError::MissingBoundary`] if it has no `boundary`
+    /// parameter, or [`MultipartError::Malformed`] if the body doesn't follow the format,
+    /// e.g. because the closing boundary is missing.
+    pub fn multipart(&self) -> Result<Vec<Part>, MultipartError> {
+        let content_type = self.header("Content-Type");
+        let Some(content_type) = content_type.filter(|value| has_media_type(value, "multipart/form-data")) else {
+            return Err(MultipartError::WrongContentType(content_type.map(str::to_owned)));
+        };
+
+        // Boundaries are 1 to 70 characters long.
+        let boundary = multipart::param(content_type, "boundary")
+            .filter(|boundary| (1..=70).contains(&boundary.len()))
+            .ok_or(MultipartError::MissingBoundary)?;
+        multipart::parse(&self.body, &boundary)
+    }
+
+    /// Deserialize the body as JSON.
+    ///
+    /// Only available with the `json` feature.
+    ///
+    /// # Errors
+    /// Returns [`JsonError::WrongContentType`] unless the `Content-Type` is `application/json`
+    /// (parameters such as `charset` are ignored), or [`JsonError::Invalid`] if the body is
+    /// malformed or doesn't fit `T`. [`JsonError::status`] gives the status to answer with.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, JsonError> {
+        let content_type = self.header("Content-Type");
+        if !content_type.is_some_and(|value| has_media_type(value, "application/json")) {
+            return Err(JsonError::WrongContentType(content_type.map(str::to_owned)));
+        }
+
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// Whether the client wants to send more requests on this connection.
+    ///
+    /// HTTP/1.1 connections stay open unless the client sends `Connection: close`;
+    /// HTTP/1.0 ones close unless the client sends `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header("Connection").unwrap_or("");
+        let has_token = |token: &str| {
+            connection
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+
+        if self.is_http_1_0() {
+            has_token("keep-alive")
+        } else {
+            !has_token("close")
+        }
+    }
+
+    /// Whether the request is HTTP/1.0, which lacks persistent connections by default,
+    /// `100 Continue` and chunked encoding. The only other version served is HTTP/1.1.
+    pub(crate) fn is_http_1_0(&self) -> bool {
+        self.version == "HTTP/1.0"
+    }
+}
+
+/// Whether a `Content-Type` value such as `text/html; charset=utf-8` names `media_type`, ignoring
+/// parameters and case.
+fn has_media_type(content_type: &str, media_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|found| found.trim().eq_ignore_ascii_case(media_type))
+}
+
+/// Read a `Transfer-Encoding: chunked` body: chunks, each a hex size line, that many bytes and
+/// a CRLF, up to a chunk of size zero, then any trailer fields, which are dropped.
+///
+/// Chunk extensions (`;name=value` after the size) are ignored. A bad size line or a chunk that
+/// doesn't end in CRLF gives [`ParseError::Malformed`], and a body that grows past
+/// `limits.max_body_size` gives [`ParseError::PayloadTooLarge`] without reading the rest of it.
+pub(crate) fn read_chunked<R: BufRead>(reader: &mut R, limits: &RequestLimits) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let mut budget = MAX_CHUNK_LINE;
+        let line = read_line(reader, &mut budget).map_err(|e| match e {
+            ParseError::HeadersTooLarge => ParseError::Malformed("chunk size line too long"),
+            e => e,
+        })?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ParseError::Malformed("bad chunk size"));
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::PayloadTooLarge)?;
+        if size == 0 {
+            break;
+        }
+        if body.len().saturating_add(size) > limits.max_body_size {
+            return Err(ParseError::PayloadTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(ParseError::Malformed("chunk not followed by CRLF"));
+        }
+    }
+
+    // Trailer fields count against the header limit.
+    let mut budget = limits.max_header_size;
+    while !read_line(reader, &mut budget)?.is_empty() {}
+    Ok(body)
+}
+
+/// Read one CRLF (or bare LF) terminated line, without the line ending, reading no more than
+/// `budget` bytes and taking what was read off it.
+///
+/// A line that doesn't end within the budget gives [`ParseError::HeadersTooLarge`].
+fn read_line<R: BufRead>(reader: &mut R, budget: &mut usize) -> Result<String, ParseError> {
+    if *budget == 0 {
+        return Err(ParseError::HeadersTooLarge);
+    }
+    let mut line = String::new();
+    let read = io::Read::take(&mut *reader, *budget as u64).read_line(&mut line)?;
+    if read == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before the request was complete",
+        )
+        .into());
+    }
+    *budget -= read;
+    if *budget == 0 && !line.ends_with('\n') {
+        return Err(ParseError::HeadersTooLarge);
+    }
+
+    let trimmed = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(trimmed);
+    Ok(line)
+}
+
+/// Whether `e` is a socket read or write timing out.
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    // Unix reports an expired socket timeout as WouldBlock, Windows as TimedOut.
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
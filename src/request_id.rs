@@ -0,0 +1,34 @@
+// Ids that tie a request's log lines to the same request seen by a proxy in front of the server.
+use std::hash::{BuildHasher, RandomState};       // For a prefix that differs between runs
+use std::sync::OnceLock;                         // For choosing the prefix once per process
+use std::sync::atomic::{AtomicU64, Ordering};    // For numbering requests
+
+use crate::request::Request;
+
+/// The longest `X-Request-Id` reused from a client; longer ones are replaced.
+const MAX_LEN: usize = 200;
+
+/// Requests numbered so far by this process.
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The id for `request`: its own `X-Request-Id` if it sent a usable one, a new one otherwise.
+///
+/// A usable id is 1 to `MAX_LEN` visible ASCII characters, so a client can't break up
+/// log lines or response headers with the id it sends.
+pub(crate) fn for_request(request: &Request) -> String {
+    match request.header("X-Request-Id") {
+        Some(id) if (1..=MAX_LEN).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic()) => {
+            id.to_owned()
+        }
+        _ => generate(),
+    }
+}
+
+/// A new id, unique within this process: a random prefix chosen at startup,
+/// then a count of the ids generated, e.g. `3f9c0a2e5b7d4e61-42`.
+pub(crate) fn generate() -> String {
+    static PREFIX: OnceLock<u64> = OnceLock::new();
+    let prefix = PREFIX.get_or_init(|| RandomState::new().hash_one(std::process::id()));
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
+    format!("{prefix:016x}-{n}")
+}
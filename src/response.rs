@@ -0,0 +1,678 @@
+// HTTP responses produced by handlers.
+use std::fs::File;                                 // For file bodies
+use std::io::{self, Read, Seek, SeekFrom, Write};  // For file bodies and writing responses to a stream
+use std::path::Path;                               // For file locations
+use std::sync::mpsc::{Receiver, RecvTimeoutError}; // For streamed bodies
+use std::sync::{Arc, Mutex};                       // For sharing an open file or stream between clones of a response
+use std::time::{Duration, SystemTime, UNIX_EPOCH}; // For file modification times, the Date header and event stream heartbeats
+
+use flate2::Compression;                           // For choosing the gzip level
+use flate2::write::GzEncoder;                      // For gzip-compressing bodies
+
+use crate::cookie::{CookieOptions, set_cookie_value};
+use crate::http_date;
+use crate::mime::content_type_for;
+use crate::request::Request;
+
+/// Bodies smaller than this aren't worth compressing: the gzip framing eats most of the saving.
+const MIN_GZIP_SIZE: usize = 1024;
+
+/// Files up to this size are read into memory, where they can be compressed;
+/// bigger ones are streamed from disk as the response is written.
+const MAX_BUFFERED_FILE: u64 = 1024 * 1024;
+
+/// How long an event stream may go quiet before a comment is sent to check the client is still there.
+const EVENT_STREAM_HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// An HTTP response to send back to a client.
+///
+/// Responses are built with chainable methods, e.g.
+/// `Response::ok().header("Content-Type", "text/plain").body("hello")`,
+/// and sent with [`Response::write_to`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// The status code, e.g. `200`.
+    pub status: u16,
+    /// Response headers, in the order they are sent. Names may repeat, as with `Set-Cookie`.
+    /// `Content-Length` is filled in when the response is written.
+    pub headers: Vec<(String, String)>,
+    /// The raw response body.
+    pub body: Vec<u8>,
+    /// A file streamed in place of `body`, for files too big to hold in memory.
+    file: Option<FileBody>,
+    /// Events or chunks sent in place of `body` as they arrive, until the sender hangs up.
+    stream: Option<StreamBody>,
+    /// How long the server holds the response back before sending it.
+    delay: Option<Duration>,
+}
+
+/// An open file sent as a response body.
+#[derive(Debug, Clone)]
+struct FileBody {
+    file: Arc<File>, // The file, opened when the response was built
+    offset: u64,     // Where in the file the body starts
+    len: u64,        // Bytes to send, from the file's size at that point
+}
+
+impl PartialEq for FileBody {
+    /// Two file bodies are equal if they send the same open file.
+    fn eq(&self, other: &FileBody) -> bool {
+        Arc::ptr_eq(&self.file, &other.file) && self.offset == other.offset && self.len == other.len
+    }
+}
+
+impl Eq for FileBody {}
+
+/// A body sent as it is produced, rather than all at once.
+#[derive(Debug, Clone)]
+enum StreamBody {
+    /// The events of a `text/event-stream` response.
+    Events(Arc<Mutex<Receiver<String>>>),
+    /// The pieces of a body of unknown length.
+    Chunks(Arc<Mutex<Receiver<Vec<u8>>>>),
+}
+
+impl PartialEq for StreamBody {
+    /// Two streams are equal if they send the same events or chunks.
+    fn eq(&self, other: &StreamBody) -> bool {
+        match (self, other) {
+            (StreamBody::Events(a), StreamBody::Events(b)) => Arc::ptr_eq(a, b),
+            (StreamBody::Chunks(a), StreamBody::Chunks(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for StreamBody {}
+
+impl Response {
+    /// Create a response with the given status and body and no headers.
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Response {
+        Response { status, headers: Vec::new(), body: body.into(), file: None, stream: None, delay: None }
+    }
+
+    /// Create an empty response with the given status.
+    pub fn with_status(status: u16) -> Response {
+        Response::new(status, Vec::new())
+    }
+
+    /// Create an empty `200 OK` response.
+    pub fn ok() -> Response {
+        Response::with_status(200)
+    }
+
+    /// Create an empty response redirecting the client to `location`.
+    ///
+    /// A permanent redirect is `301 Moved Permanently`, which clients and caches may remember;
+    /// a temporary one is `302 Found`. Like any header value, `location` loses its CR, LF and NUL
+    /// characters (see [`Response::header`]), so one built from request data can't add headers.
+    pub fn redirect(location: &str, permanent: bool) -> Response {
+        let status = if permanent { 301 } else { 302 };
+        Response::with_status(status).header("Location", location)
+    }
+
+    /// Create a `200 OK` response that streams server-sent events to the client as they are
+    /// sent on the other end of `events`, with `Content-Type: text/event-stream`.
+    ///
+    /// Each message becomes one event, e.g. `data: price=42\n\n`; a message with several lines
+    /// is sent as several `data:` lines of one event. The response has no `Content-Length`, so
+    /// the connection is closed once the sender is dropped and the stream ends. A comment is sent
+    /// whenever no event has been for `EVENT_STREAM_HEARTBEAT`, so a client that has gone away is
+    /// noticed, and sending to `events` then fails.
+    ///
+    /// The worker writing the stream is tied to it until then, so a pool of `n` workers can serve
+    /// fewer than `n` streams at once and still answer other requests. Size the pool for the
+    /// number of clients expected to be listening, or let it grow with
+    /// [`ThreadPoolBuilder::max_workers`](crate::ThreadPoolBuilder::max_workers).
+    pub fn event_stream(events: Receiver<String>) -> Response {
+        let mut response = Response::ok()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache");
+        response.stream = Some(StreamBody::Events(Arc::new(Mutex::new(events))));
+        response
+    }
+
+    /// Create a `200 OK` response whose body is sent a piece at a time as the pieces are sent on
+    /// the other end of `chunks`, for bodies whose length isn't known up front.
+    ///
+    /// The body is sent with `Transfer-Encoding: chunked`, each piece as one chunk (empty pieces
+    /// are skipped), and ends when the sender is dropped. HTTP/1.0 clients, which don't know
+    /// chunked encoding, get the pieces as they are and the connection closed at the end.
+    /// Sending to `chunks` fails once the client has gone away.
+    ///
+    /// Like an [`Response::event_stream`], the response holds its worker until it ends.
+    pub fn chunked(chunks: Receiver<Vec<u8>>) -> Response {
+        let mut response = Response::ok().header("Transfer-Encoding", "chunked");
+        response.stream = Some(StreamBody::Chunks(Arc::new(Mutex::new(chunks))));
+        response
+    }
+
+    /// Send the response only once `delay` has passed since the handler returned it, e.g. to slow
+    /// down repeated failed logins.
+    ///
+    /// The wait doesn't hold up a worker: the connection, plain or TLS, is parked on the pool's
+    /// timer thread until the response is due. Once it has been sent, the connection is kept
+    /// open for further requests (or handed to a WebSocket handler after a `101`) as usual.
+    ///
+    /// Any delay is accepted. One too long to add to the current time, such as [`Duration::MAX`],
+    /// is held for a century instead (see [`ThreadPool::execute_after`](crate::ThreadPool::execute_after)),
+    /// so in practice the response is never sent and the connection is dropped at shutdown.
+    pub fn delayed(mut self, delay: Duration) -> Response {
+        self.delay = Some(delay).filter(|delay| !delay.is_zero());
+        self
+    }
+
+    /// How long to hold the response back, as set by [`Response::delayed`].
+    pub(crate) fn delay(&self) -> Option<Duration> {
+        self.delay
+    }
+
+    /// Whether the body only ends when the connection is closed: an event stream,
+    /// or a chunked response turned into a plain stream by [`Response::unchunked`].
+    pub(crate) fn is_close_delimited(&self) -> bool {
+        match self.stream {
+            Some(StreamBody::Events(_)) => true,
+            Some(StreamBody::Chunks(_)) => !self.is_chunked(),
+            None => false,
+        }
+    }
+
+    /// Send a [`Response::chunked`] body as plain bytes instead, for an HTTP/1.0 client.
+    pub(crate) fn unchunked(mut self) -> Response {
+        self.headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Transfer-Encoding"));
+        self
+    }
+
+    /// Whether the body is written with chunked transfer encoding.
+    fn is_chunked(&self) -> bool {
+        self.header_value("Transfer-Encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+    }
+
+    /// Create a response whose body is the contents of the file at `path`,
+    /// with a `Content-Type` matching the file's extension.
+    ///
+    /// Files bigger than `MAX_BUFFERED_FILE` aren't read into `body`: the open file is kept
+    /// and copied to the client a buffer at a time when the response is written, so `body`
+    /// stays empty and the response isn't compressed. [`Response::body_len`] counts it either way.
+    ///
+    /// The response advertises `Accept-Ranges: bytes`, so [`Response::ranged_for`] can answer
+    /// requests for part of the file. Where the platform reports the file's modification time,
+    /// the response also gets a `Last-Modified` header and an `ETag` built from the time and the
+    /// file's size, for [`Response::conditional_for`].
+    ///
+    /// # Errors
+    /// Returns the io error if the file cannot be opened or read.
+    pub fn from_file(status: u16, path: &str) -> io::Result<Response> {
+        Response::from_path(status, Path::new(path))
+    }
+
+    /// [`Response::from_file`] for a `Path`.
+    pub(crate) fn from_path(status: u16, path: &Path) -> io::Result<Response> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+
+        let mut response = Response::with_status(status);
+        if let Ok(modified) = metadata.modified() {
+            response = response
+                .header("Last-Modified", &http_date::format(modified))
+                .header("ETag", &file_etag(modified, len));
+        }
+        if len > MAX_BUFFERED_FILE {
+            response.file = Some(FileBody { file: Arc::new(file), offset: 0, len });
+        } else {
+            file.read_to_end(&mut response.body)?;
+        }
+        Ok(response
+            .header("Content-Type", content_type_for(&path.to_string_lossy()))
+            .header("Accept-Ranges", "bytes"))
+    }
+
+    /// Like [`Response::from_file`], but a file that can't be read is logged and answered
+    /// with an empty `500 Internal Server Error` instead of returning an error.
+    /// The router fills in its 500 page when the response is dispatched.
+    pub fn from_file_or_error(status: u16, path: &str) -> Response {
+        Response::from_file(status, path).unwrap_or_else(|e| {
+            log::warn!("Failed to read {path}: {e}");
+            Response::with_status(500)
+        })
+    }
+
+    /// Create a `200 OK` response whose body is `value` serialized as JSON,
+    /// with `Content-Type: application/json`.
+    ///
+    /// Only available with the `json` feature.
+    ///
+    /// # Errors
+    /// Returns the serializer's error if `value` can't be represented as JSON,
+    /// e.g. a map with non-string keys.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(value: &T) -> Result<Response, serde_json::Error> {
+        let body = serde_json::to_vec(value)?;
+        Ok(Response::new(200, body).header("Content-Type", "application/json"))
+    }
+
+    /// Add a header, keeping any earlier ones with the same name.
+    ///
+    /// Use [`Response::set_header`] for headers that must appear only once.
+    ///
+    /// CR, LF and NUL characters are removed from `name` and `value`: written out, they would
+    /// end the header early, letting a value taken from a request add headers of its own or
+    /// split the response in two.
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((header_safe(name), header_safe(value)));
+        self
+    }
+
+    /// Set a header, replacing every earlier one with the same name (ignoring case).
+    pub fn set_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.header(name, value)
+    }
+
+    /// Set a cookie on the client by adding a `Set-Cookie` header, e.g.
+    /// `Set-Cookie: session=abc123; Path=/; HttpOnly; SameSite=Lax`.
+    ///
+    /// Each call adds another header, so several cookies can be set at once.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or not a valid token, or `value` contains whitespace,
+    /// `"`, `,`, `;`, `\` or control characters. Encode such values first, e.g. as hex.
+    pub fn set_cookie(self, name: &str, value: &str, options: &CookieOptions) -> Response {
+        self.header("Set-Cookie", &set_cookie_value(name, value, options))
+    }
+
+    /// Replace the body, including one streamed from a file or an event stream.
+    ///
+    /// Replacing a [`Response::chunked`] body drops its `Transfer-Encoding: chunked` as well,
+    /// since the new body is sent with a `Content-Length` instead.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = body.into();
+        self.file = None;
+        if self.stream.take().is_some() {
+            self = self.unchunked();
+        }
+        self
+    }
+
+    /// The length of the body in bytes, whether it's held in `body` or streamed from a file.
+    pub fn body_len(&self) -> u64 {
+        match &self.file {
+            Some(file) => file.len,
+            None => self.body.len() as u64,
+        }
+    }
+
+    /// Add `field` to the `Vary` header, keeping any fields already listed there.
+    ///
+    /// This tells caches that the response depends on that request header.
+    pub fn vary(self, field: &str) -> Response {
+        let value = match self.header_value("Vary") {
+            Some(vary) if vary.split(',').any(|listed| listed.trim().eq_ignore_ascii_case(field)) => return self,
+            Some(vary) => format!("{vary}, {field}"),
+            None => field.to_owned(),
+        };
+        self.set_header("Vary", &value)
+    }
+
+    /// Look up a header value by name, ignoring case. If the header repeats, this is the first value.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Turn the response into `304 Not Modified` if `req` already has an up-to-date copy of it.
+    ///
+    /// Applies to `200 OK` responses to `GET` and `HEAD`. If the request has an `If-None-Match`
+    /// header, the copy is up to date when one of the tags listed there (or `*`) matches the
+    /// response's `ETag`. Otherwise the request's `If-Modified-Since` date must be no older than
+    /// the response's `Last-Modified`; dates that can't be parsed are ignored.
+    /// The body is dropped and the other headers kept.
+    pub fn conditional_for(mut self, req: &Request) -> Response {
+        if self.status != 200 || !req.method.is_get_or_head() {
+            return self;
+        }
+
+        // A client that sends both trusts the tags over the dates.
+        let fresh = match req.header("If-None-Match") {
+            Some(tags) => self.header_value("ETag").is_some_and(|etag| etag_matches(tags, etag)),
+            None => {
+                let last_modified = self.header_value("Last-Modified").and_then(http_date::parse);
+                let since = req.header("If-Modified-Since").and_then(http_date::parse);
+                matches!((last_modified, since), (Some(last_modified), Some(since)) if last_modified <= since)
+            }
+        };
+        if !fresh {
+            return self;
+        }
+
+        self.status = 304;
+        self.body(Vec::new())
+    }
+
+    /// Cut the body down to the byte range asked for by `req`'s `Range` header, if any.
+    ///
+    /// Only `200 OK` responses to `GET` and `HEAD` that advertise `Accept-Ranges: bytes` (as
+    /// [`Response::from_file`] does) are cut. A single range such as `bytes=0-499`, `bytes=500-`
+    /// or `bytes=-500` (the last 500 bytes) turns the response into `206 Partial Content` with a
+    /// `Content-Range` header; a file body is seeked into rather than read. A range starting past
+    /// the end gets an empty `416 Range Not Satisfiable`. Malformed headers and requests for several
+    /// ranges at once are ignored, and the whole body is sent.
+    pub fn ranged_for(mut self, req: &Request) -> Response {
+        let Some(range) = req.header("Range") else {
+            return self;
+        };
+        if self.status != 200
+            || !req.method.is_get_or_head()
+            || !self.header_value("Accept-Ranges").is_some_and(|value| value.eq_ignore_ascii_case("bytes"))
+        {
+            return self;
+        }
+
+        let len = self.body_len();
+        match parse_range(range, len) {
+            None => self,
+            Some(ByteRange::Unsatisfiable) => {
+                self.status = 416;
+                self.body(Vec::new()).set_header("Content-Range", &format!("bytes */{len}"))
+            }
+            Some(ByteRange::Satisfiable(start, end)) => {
+                self.status = 206;
+                match &mut self.file {
+                    Some(file) => {
+                        file.offset += start;
+                        file.len = end - start + 1;
+                    }
+                    // In-memory bodies are at most `usize` long, so the casts can't truncate.
+                    None => self.body = self.body[start as usize..=end as usize].to_vec(),
+                }
+                self.set_header("Content-Range", &format!("bytes {start}-{end}/{len}"))
+            }
+        }
+    }
+
+    /// Gzip the body if `req` accepts it and compressing is worthwhile.
+    ///
+    /// The body is compressed, and `Content-Encoding: gzip` set, only when the request's
+    /// `Accept-Encoding` allows gzip, the body is at least `MIN_GZIP_SIZE` bytes, the
+    /// `Content-Type` is text-like (images and other binary formats are usually compressed
+    /// already), no `Content-Encoding` is set yet, and the body isn't a [`Response::ranged_for`] slice. `Content-Length` follows the compressed
+    /// body when the response is written. Compressible responses also get `Vary: Accept-Encoding`
+    /// so caches keep the two variants apart.
+    pub fn compressed_for(mut self, req: &Request) -> Response {
+        if self.body.len() < MIN_GZIP_SIZE
+            || self.header_value("Content-Encoding").is_some()
+            || self.header_value("Content-Range").is_some()
+            || !self.header_value("Content-Type").is_some_and(is_compressible)
+        {
+            return self;
+        }
+
+        self = self.vary("Accept-Encoding");
+        if !req.header("Accept-Encoding").is_some_and(accepts_gzip) {
+            return self;
+        }
+
+        // Writing into a Vec can't fail; if the encoder somehow does, send the body as it was.
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        match encoder.write_all(&self.body).and_then(|()| encoder.finish()) {
+            Ok(compressed) => self.body(compressed).header("Content-Encoding", "gzip"),
+            Err(_) => self,
+        }
+    }
+
+    /// Serialize the response as HTTP/1.1 and write it to `stream`.
+    ///
+    /// Writes the status line, the headers in the order they were added, a `Date` header with the
+    /// current time unless the response already has one, a `Content-Length` header matching the
+    /// body (except for statuses that never have one, such as 204 and 304), a blank line, and
+    /// finally the body. An [`Response::event_stream`] or [`Response::chunked`]
+    /// body has no `Content-Length` and is written as it arrives, returning only once it ends.
+    /// The status line always says `HTTP/1.1`, the server's own version, which HTTP/1.0 clients
+    /// understand too.
+    /// Any `Content-Length` set by hand is ignored in favour of the real body length, counted in
+    /// bytes, so text with multibyte UTF-8 characters is framed correctly.
+    ///
+    /// The head goes out in many small writes, so give this a buffered stream, such as a
+    /// [`BufWriter`](std::io::BufWriter) around a socket, and flush it afterwards.
+    ///
+    /// # Errors
+    /// Returns any io error raised while writing. A file body that has shrunk since the
+    /// response was built gives `UnexpectedEof` once the file runs out, since the promised
+    /// `Content-Length` can't be met and the connection must be closed; if it has grown,
+    /// only the original length is sent.
+    pub fn write_to<W: Write>(&self, stream: &mut W) -> io::Result<()> {
+        self.write_head_to(stream)?;
+        match &self.stream {
+            Some(StreamBody::Events(events)) => return write_events(events, stream),
+            Some(StreamBody::Chunks(chunks)) => return write_chunks(chunks, self.is_chunked(), stream),
+            None => {}
+        }
+        let Some(body) = &self.file else {
+            return stream.write_all(&self.body);
+        };
+
+        // Clones of the response share the file, so seek to the body every time.
+        let mut file = &*body.file;
+        file.seek(SeekFrom::Start(body.offset))?;
+        let sent = io::copy(&mut file.take(body.len), stream)?;
+        if sent < body.len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while being sent"));
+        }
+        Ok(())
+    }
+
+    /// Write everything [`Response::write_to`] would except the body, as the answer to a `HEAD` request.
+    ///
+    /// `Content-Length` still gives the length of the body that a `GET` would have received.
+    ///
+    /// # Errors
+    /// Returns any io error raised while writing.
+    pub fn write_head_to<W: Write>(&self, stream: &mut W) -> io::Result<()> {
+        // The lines must be separated by CRLF (\r\n) as per HTTP protocol,
+        // with a blank line between the headers and the body.
+        let status = self.status;
+        write!(stream, "HTTP/1.1 {status} {}\r\n", reason_phrase(status))?;
+
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                write!(stream, "{name}: {value}\r\n")?;
+            }
+        }
+        if self.header_value("Date").is_none() {
+            write!(stream, "Date: {}\r\n", http_date::format(SystemTime::now()))?;
+        }
+        if has_body(status) && self.stream.is_none() {
+            write!(stream, "Content-Length: {}\r\n", self.body_len())?;
+        }
+        stream.write_all(b"\r\n")
+    }
+}
+
+/// The outcome of a `Range` header that asks for one byte range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// The first and last byte to send, inclusive.
+    Satisfiable(u64, u64),
+    /// The range lies entirely past the end of the body.
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header value such as `bytes=0-499` against a body of `len` bytes.
+///
+/// Returns `None` for anything that should be ignored: other units, several ranges,
+/// or malformed values. An end past the body is clipped to its last byte.
+fn parse_range(value: &str, len: u64) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // A suffix range: the last `end` bytes.
+        let suffix: u64 = end.parse().ok()?;
+        return Some(match suffix {
+            0 => ByteRange::Unsatisfiable,
+            _ if len == 0 => ByteRange::Unsatisfiable,
+            _ => ByteRange::Satisfiable(len.saturating_sub(suffix), len - 1),
+        });
+    }
+
+    let start: u64 = start.parse().ok()?;
+    let end = match end {
+        "" => u64::MAX,
+        end => end.parse().ok()?,
+    };
+    if end < start {
+        return None;
+    }
+    if start >= len {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Satisfiable(start, end.min(len - 1)))
+}
+
+/// Write each event from `events` to `stream` as it arrives, until the sender hangs up.
+///
+/// Events are flushed one at a time so the client sees them straight away.
+fn write_events<W: Write>(events: &Mutex<Receiver<String>>, stream: &mut W) -> io::Result<()> {
+    // A poisoned lock still holds a usable receiver.
+    let events = events.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let chunk = match events.recv_timeout(EVENT_STREAM_HEARTBEAT) {
+            Ok(event) => {
+                let mut chunk = String::new();
+                for line in event.lines() {
+                    chunk.push_str("data: ");
+                    chunk.push_str(line);
+                    chunk.push('\n');
+                }
+                chunk.push('\n');
+                chunk
+            }
+            // Lines starting with a colon are comments, which clients ignore.
+            Err(RecvTimeoutError::Timeout) => ":\n\n".to_owned(),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        stream.write_all(chunk.as_bytes())?;
+        stream.flush()?;
+    }
+}
+
+/// Write each chunk from `chunks` to `stream` as it arrives, until the sender hangs up.
+///
+/// With `chunked` set, each is framed as a chunk (its length in hex, CRLF, the data, CRLF) and the
+/// body ends with the empty last chunk; otherwise the data is written as it is. Chunks are flushed
+/// one at a time so the client sees them straight away.
+fn write_chunks<W: Write>(chunks: &Mutex<Receiver<Vec<u8>>>, chunked: bool, stream: &mut W) -> io::Result<()> {
+    // A poisoned lock still holds a usable receiver.
+    let chunks = chunks.lock().unwrap_or_else(|e| e.into_inner());
+    // An empty chunk would end the body early.
+    for chunk in chunks.iter().filter(|chunk| !chunk.is_empty()) {
+        if chunked {
+            write!(stream, "{:X}\r\n", chunk.len())?;
+            stream.write_all(&chunk)?;
+            stream.write_all(b"\r\n")?;
+        } else {
+            stream.write_all(&chunk)?;
+        }
+        stream.flush()?;
+    }
+    if chunked {
+        stream.write_all(b"0\r\n\r\n")?;
+    }
+    stream.flush()
+}
+
+/// An `ETag` for a file from its modification time and size: it changes whenever either does.
+fn file_etag(modified: SystemTime, len: u64) -> String {
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("\"{:x}.{:x}-{len:x}\"", since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+/// Whether an `If-None-Match` header value, e.g. `"abc", W/"def"`, lists `etag` or is `*`.
+///
+/// Tags are compared weakly, as `If-None-Match` requires: a `W/` prefix on either side is ignored.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.trim().trim_start_matches("W/")
+    }
+    if_none_match.trim() == "*" || if_none_match.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+/// `text` without CR, LF or NUL, which can't appear in a header's name or value.
+fn header_safe(text: &str) -> String {
+    text.chars().filter(|c| !matches!(c, '\r' | '\n' | '\0')).collect()
+}
+
+/// Whether responses with this status carry a body, and so a `Content-Length`.
+fn has_body(status: u16) -> bool {
+    !matches!(status, 100..=199 | 204 | 304)
+}
+
+/// Whether a body of this `Content-Type` is likely to shrink when gzipped.
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    mime.starts_with("text/")
+        || matches!(
+            mime.as_str(),
+            "application/json" | "application/javascript" | "application/xml" | "image/svg+xml"
+        )
+}
+
+/// Whether an `Accept-Encoding` header value such as `gzip, deflate;q=0.5` allows gzip.
+///
+/// `gzip;q=0` is a refusal; `*` accepts any encoding.
+fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding.split(',').any(|item| {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or("").trim();
+        let refused = params.any(|param| {
+            param
+                .trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        (coding.eq_ignore_ascii_case("gzip") || coding == "*") && !refused
+    })
+}
+
+/// The standard reason phrase for a status code, e.g. `Not Found` for 404.
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,481 @@
+// Routing of requests to the handler registered for their method and path.
+use std::collections::{BTreeMap, BTreeSet, HashMap}; // For the routing table and the methods it serves
+use std::path::PathBuf;                              // For the static directory's root
+use std::sync::Arc;                                  // For sharing the server's shutdown flag and metrics
+use std::sync::atomic::{AtomicBool, Ordering};       // For checking whether the server is shutting down
+
+use crate::error_pages::{self, ErrorPage};
+use crate::handler::Handler;
+use crate::metrics::Metrics;
+use crate::middleware::Middleware;
+use crate::request::{Method, Request};
+use crate::response::Response;
+use crate::static_files::StaticDir;
+use crate::websocket::{self, WebSocket};
+
+/// The path of the built-in health check.
+const HEALTH_CHECK_PATH: &str = "/healthz";
+
+/// The path of the built-in Prometheus metrics.
+const METRICS_PATH: &str = "/metrics";
+
+/// A path's handlers, keyed by method.
+type MethodHandlers = BTreeMap<Method, Arc<dyn Handler>>;
+
+/// A WebSocket handler: runs for as long as the connection should stay open.
+pub(crate) type WebSocketHandler = Box<dyn Fn(&Request, &mut WebSocket<'_>) + Send + Sync>;
+
+/// A routing table mapping an exact path and a method to a handler.
+///
+/// Build it once at startup and share it between workers, e.g. behind an `Arc`.
+///
+/// `GET /healthz` is answered without a route of its own, for load balancer and orchestrator
+/// health checks: `200 OK` with the body `ok` while the server is running, and
+/// `503 Service Unavailable` once a [`Server`](crate::Server) using the router is shutting down.
+/// Adding a route for `/healthz` replaces the built-in check.
+///
+/// Likewise, `GET /metrics` reports the server's request counts and its pool's job counts
+/// in the Prometheus text format, once the router is given to a [`Server`](crate::Server).
+/// Adding a route for `/metrics` replaces it.
+///
+/// `OPTIONS *`, which asks about the server rather than a path, gets `204 No Content` with an
+/// `Allow` header listing every method some route answers, along with `GET`, `HEAD` and `OPTIONS`.
+/// Any other method with `*` for a path gets `400 Bad Request`.
+///
+/// A router made with [`Router::with_state`] carries application state of type `S`, which
+/// [`Router::add_route_with_state`] hands to its handlers; one made with [`Router::new`] has none.
+#[derive(Default)]
+pub struct Router<S = ()> {
+    routes: HashMap<String, MethodHandlers>,       // Handlers keyed by path, then method
+    patterns: Vec<(Pattern, MethodHandlers)>,      // Handlers for paths with parameters, in the order added
+    websockets: HashMap<String, WebSocketHandler>, // WebSocket handlers keyed by path
+    static_dir: Option<StaticDir>,                 // Files served for GET requests no route matches
+    error_pages: HashMap<u16, ErrorPage>,          // Bodies for error responses left empty
+    middleware: Vec<Box<dyn Middleware>>,          // Run around every request, outermost first
+    fallback: Option<Box<dyn Handler>>,            // Answers requests for paths with no route (None = 404)
+    shutdown: Option<Arc<AtomicBool>>,             // The server's shutdown flag, for /healthz
+    metrics: Option<Arc<Metrics>>,                 // The server's counters, for /metrics
+    state: Arc<S>,                                 // Application state shared with stateful routes
+}
+
+impl Router {
+    /// Create an empty router. Every request gets a 404 until routes are added.
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Create an empty router whose routes can share `state`, such as configuration, counters
+    /// or a cache, through [`Router::add_route_with_state`].
+    ///
+    /// It is kept in an `Arc` and shared by every worker, so anything in it that changes needs
+    /// to be behind atomics or a lock.
+    pub fn with_state<S: Send + Sync + 'static>(state: S) -> Router<S> {
+        Router::new().with_shared_state(Arc::new(state))
+    }
+}
+
+impl<S> Router<S> {
+    /// Register `handler` for requests with the given method and path. For a handler that is
+    /// a struct with state of its own, see [`Router::add_handler`].
+    ///
+    /// A path segment written `:name` matches any one non-empty segment, and a last segment
+    /// written `*name` matches the rest of the path, slashes included; the handler finds what
+    /// they matched with [`Request::param`]. For example `/users/:id` matches
+    /// `/users/42` with `id` set to `42`, and `/files/*path` matches `/files/docs/a.txt` with
+    /// `path` set to `docs/a.txt`. A bare `*` works too, for mounting something under a prefix:
+    /// `/static/*` matches everything below `/static/`, and what follows is `req.param("*")`.
+    /// Other paths only match exactly.
+    ///
+    /// When several routes match, a plain segment beats a `:name`, which beats a `*name`,
+    /// comparing the routes from their first segment on: `/users/me` is preferred over
+    /// `/users/:id` for `/users/me`. Registering the same method and path twice replaces
+    /// the earlier handler.
+    ///
+    /// # Panics
+    /// Panics if a `:name` has no name, or a `*name` or `*` isn't the last segment.
+    pub fn add_route<F>(&mut self, method: Method, path: &str, handler: F)
+    where F: Fn(&Request) -> Response + Send + Sync + 'static, {
+        self.add_handler(method, path, Arc::new(handler));
+    }
+
+    /// Register a [`Handler`] for requests with the given method and path, such as a struct
+    /// holding state shared between requests. Paths work as for [`Router::add_route`].
+    ///
+    /// The same `Arc` can be registered for several routes, which then share its state.
+    ///
+    /// # Panics
+    /// Panics if a `:name` has no name, or a `*name` or `*` isn't the last segment.
+    pub fn add_handler(&mut self, method: Method, path: &str, handler: Arc<dyn Handler>) {
+        let handlers = match Pattern::parse(path) {
+            None => self.routes.entry(path.to_owned()).or_default(),
+            Some(pattern) => match self.patterns.iter().position(|(existing, _)| *existing == pattern) {
+                Some(pos) => &mut self.patterns[pos].1,
+                None => {
+                    self.patterns.push((pattern, BTreeMap::new()));
+                    &mut self.patterns.last_mut().unwrap().1
+                }
+            },
+        };
+        handlers.insert(method, handler);
+    }
+
+    /// Register `handler` like [`Router::add_route`], passing it the router's state (see
+    /// [`Router::with_state`]) along with each request.
+    ///
+    /// # Panics
+    /// Panics if the path is invalid as for [`Router::add_route`].
+    pub fn add_route_with_state<F>(&mut self, method: Method, path: &str, handler: F)
+    where
+        S: Send + Sync + 'static,
+        F: Fn(&Request, &S) -> Response + Send + Sync + 'static,
+    {
+        let state = Arc::clone(&self.state);
+        self.add_route(method, path, move |req| handler(req, &state));
+    }
+
+    /// The same routes, with `state` in place of the router's own. Handlers added with
+    /// [`Router::add_route_with_state`] hold on to the state they were given.
+    fn with_shared_state<T>(self, state: Arc<T>) -> Router<T> {
+        Router {
+            routes: self.routes,
+            patterns: self.patterns,
+            websockets: self.websockets,
+            static_dir: self.static_dir,
+            error_pages: self.error_pages,
+            middleware: self.middleware,
+            fallback: self.fallback,
+            shutdown: self.shutdown,
+            metrics: self.metrics,
+            state,
+        }
+    }
+
+    /// The same routes, without the state: what a [`Server`](crate::Server) runs, whatever its
+    /// router's state was.
+    pub(crate) fn into_stateless(self) -> Router {
+        self.with_shared_state(Arc::new(()))
+    }
+
+    /// Accept WebSocket connections on `path`, handing each upgraded connection to `handler`
+    /// along with the request that opened it.
+    ///
+    /// The handshake goes through the middleware like any other request, so a middleware can
+    /// refuse it; if it answers with anything but `101 Switching Protocols`, the handler isn't run.
+    /// Requests to `path` that don't ask for an upgrade go to its ordinary routes, if it has any,
+    /// and get `426 Upgrade Required` otherwise. The connection is closed when `handler` returns.
+    ///
+    /// Each connection holds a worker for as long as the handler runs, so size the pool with the
+    /// number of open WebSockets in mind. Registering the same path twice replaces the earlier handler.
+    pub fn websocket<F>(&mut self, path: &str, handler: F)
+    where F: Fn(&Request, &mut WebSocket<'_>) + Send + Sync + 'static, {
+        self.websockets.insert(path.to_owned(), Box::new(handler));
+    }
+
+    /// Answer requests for paths without a route with `handler`, instead of an empty 404,
+    /// e.g. to proxy them elsewhere or render a 404 page that depends on the request.
+    ///
+    /// The fallback runs after the built-in `/healthz` and `/metrics` and the static directory
+    /// have had their turn, for any method. A path that has routes, but not for the request's
+    /// method, still gets `405 Method Not Allowed`. Setting a fallback again replaces the earlier one.
+    pub fn fallback<F>(&mut self, handler: F)
+    where F: Fn(&Request) -> Response + Send + Sync + 'static, {
+        self.fallback = Some(Box::new(handler));
+    }
+
+    /// Report the server as shutting down on `/healthz` once `flag` is set.
+    pub(crate) fn watch_shutdown(&mut self, flag: Arc<AtomicBool>) {
+        self.shutdown = Some(flag);
+    }
+
+    /// Serve `metrics` on `/metrics`.
+    pub(crate) fn watch_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// The WebSocket handler for `path`, if any.
+    pub(crate) fn websocket_handler(&self, path: &str) -> Option<&WebSocketHandler> {
+        self.websockets.get(path)
+    }
+
+    /// Serve files from `root` for `GET` requests that don't match a route.
+    ///
+    /// `GET /foo/bar.css` serves `root/foo/bar.css`; see [`StaticDir::serve`] for how paths
+    /// are resolved and which requests are refused. Calling this again replaces the directory.
+    pub fn serve_dir(&mut self, root: impl Into<PathBuf>) {
+        self.static_dir = Some(StaticDir::new(root));
+    }
+
+    /// Send `page` as the body of every `status` response that would otherwise be empty,
+    /// e.g. `router.error_page(404, ErrorPage::File("pages/404.html".into()))`.
+    ///
+    /// Without a page of their own, 404 and 500 responses get a minimal built-in HTML page
+    /// and other statuses stay empty. Responses whose handler already set a body are left alone.
+    pub fn error_page(&mut self, status: u16, page: ErrorPage) {
+        self.error_pages.insert(status, page);
+    }
+
+    /// Run `middleware` around every request this router dispatches.
+    ///
+    /// Middleware runs in the order it was added: the first one added sees the request first
+    /// and the response last. The response it sees already has its error page filled in.
+    pub fn add_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Run the request through the middleware and into the handler matching its path and method,
+    /// filling in the error page for an empty error response.
+    pub fn dispatch(&self, req: &Request) -> Response {
+        if self.middleware.is_empty() {
+            self.respond(req)
+        } else {
+            self.run_middleware(0, req.clone())
+        }
+    }
+
+    /// Run the middleware from `index` onwards, then the handler.
+    fn run_middleware(&self, index: usize, req: Request) -> Response {
+        match self.middleware.get(index) {
+            Some(middleware) => middleware.call(req, &|req| self.run_middleware(index + 1, req)),
+            None => self.respond(&req),
+        }
+    }
+
+    /// Run the handler matching the request's path and method, filling in the error page
+    /// for an empty error response.
+    fn respond(&self, req: &Request) -> Response {
+        let response = self.route(req);
+        if response.status < 400 || response.body_len() > 0 {
+            return response;
+        }
+
+        match self.error_pages.get(&response.status) {
+            Some(page) => page.apply(response),
+            None => error_pages::apply_builtin(response),
+        }
+    }
+
+    /// Run the handler matching the request's path and method.
+    ///
+    /// WebSocket upgrades to a WebSocket path get the handshake response.
+    /// A `HEAD` request without a `HEAD` route of its own is answered like a `GET` to the same path;
+    /// the server then sends the headers without the body. If the path has routes but none for
+    /// this method, the response is `405 Method Not Allowed` with an `Allow` header listing the
+    /// methods that are. Unknown paths try the built-in health check and metrics, then the static
+    /// directory for `GET` requests, and finally go to the fallback handler, or get an empty 404.
+    fn route(&self, req: &Request) -> Response {
+        if req.path == "*" {
+            return match req.method {
+                Method::Options => self.server_options(),
+                _ => Response::with_status(400),
+            };
+        }
+
+        if self.websockets.contains_key(&req.path) {
+            if websocket::is_upgrade(req) {
+                return websocket::accept(req);
+            }
+            if !self.routes.contains_key(&req.path) {
+                return Response::with_status(426).header("Upgrade", "websocket");
+            }
+        }
+
+        let Some((handlers, params)) = self.find_route(&req.path) else {
+            if req.path == HEALTH_CHECK_PATH && req.method.is_get_or_head() {
+                return self.health_check();
+            }
+            if req.path == METRICS_PATH
+                && req.method.is_get_or_head()
+                && let Some(metrics) = &self.metrics
+            {
+                return Response::new(200, metrics.render())
+                    .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+                    .header("Cache-Control", "no-store");
+            }
+            let file = match &self.static_dir {
+                Some(dir) if req.method.is_get_or_head() => dir.serve(&req.path),
+                _ => None,
+            };
+            return file.unwrap_or_else(|| match &self.fallback {
+                Some(fallback) => fallback.handle(req),
+                None => Response::with_status(404),
+            });
+        };
+
+        match handler_for(handlers, &req.method) {
+            Some(handler) if params.is_empty() => handler.handle(req),
+            Some(handler) => handler.handle(&Request { params, ..req.clone() }),
+            None => method_not_allowed(handlers),
+        }
+    }
+
+    /// Whether the router answers `req` with an error of its own, decided from the method and
+    /// path alone: a `404` for a path that nothing serves, a `405` for a method its route has
+    /// no handler for, or a `400` for a `*` path other than `OPTIONS *`. The body can't change
+    /// that, so the server uses this to turn down a client waiting on `Expect: 100-continue`
+    /// before it sends one. Paths the static directory, the fallback, the built-in checks or a
+    /// WebSocket may answer are never refused here.
+    pub(crate) fn refuses(&self, req: &Request) -> bool {
+        if req.path == "*" {
+            return req.method != Method::Options;
+        }
+        if self.websockets.contains_key(&req.path) {
+            return false;
+        }
+        match self.find_route(&req.path) {
+            Some((handlers, _)) => handler_for(handlers, &req.method).is_none(),
+            None => {
+                let builtin = req.path == HEALTH_CHECK_PATH || req.path == METRICS_PATH;
+                let file = self.static_dir.is_some();
+                self.fallback.is_none() && !(req.method.is_get_or_head() && (builtin || file))
+            }
+        }
+    }
+
+    /// The handlers of the route for `path`, along with the values of its parameters:
+    /// the exact route if there is one, or else the best matching route with parameters.
+    fn find_route(&self, path: &str) -> Option<(&MethodHandlers, HashMap<String, String>)> {
+        match self.routes.get(path) {
+            Some(handlers) => Some((handlers, HashMap::new())),
+            None => self.match_pattern(path),
+        }
+    }
+
+    /// The handlers of the best route with parameters matching `path`, along with the values
+    /// of its parameters; see [`Router::add_route`] for which route is best.
+    fn match_pattern(&self, path: &str) -> Option<(&MethodHandlers, HashMap<String, String>)> {
+        self.patterns
+            .iter()
+            .filter_map(|(pattern, handlers)| pattern.matches(path).map(|params| (pattern, handlers, params)))
+            .min_by_key(|(pattern, _, _)| pattern.rank())
+            .map(|(_, handlers, params)| (handlers, params))
+    }
+
+    /// The built-in health check's answer: 503 once the server is shutting down, 200 until then.
+    fn health_check(&self) -> Response {
+        let draining = self.shutdown.as_ref().is_some_and(|flag| flag.load(Ordering::SeqCst));
+        let (status, body) = if draining { (503, "shutting down") } else { (200, "ok") };
+        Response::new(status, body)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .header("Cache-Control", "no-store")
+    }
+
+    /// The response to `OPTIONS *`: the methods of every route, plus `GET` and `HEAD` for the
+    /// built-in health check and `OPTIONS` itself, in a fixed order.
+    fn server_options(&self) -> Response {
+        let mut allowed: BTreeSet<Method> = BTreeSet::from([Method::Get, Method::Head, Method::Options]);
+        for handlers in self.routes.values().chain(self.patterns.iter().map(|(_, handlers)| handlers)) {
+            allowed.extend(handlers.keys().cloned());
+        }
+
+        let allow: Vec<&str> = allowed.iter().map(Method::as_str).collect();
+        Response::with_status(204).header("Allow", &allow.join(", "))
+    }
+}
+
+/// A route path with parameters, e.g. `/users/:id/files/*path`.
+#[derive(Debug, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<Segment>, // The path's segments after the leading `/`
+}
+
+/// One segment of a [`Pattern`], in order of precedence.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Static(String),   // Matches exactly this text
+    Param(String),    // `:name`: matches any one non-empty segment
+    Wildcard(String), // `*name`: matches the rest of the path
+}
+
+impl Pattern {
+    /// Parse `path` as a pattern, or `None` if it has no parameters and is matched exactly.
+    ///
+    /// # Panics
+    /// Panics if a parameter has no name, or a wildcard isn't the last segment.
+    /// A wildcard without a name is named `*`.
+    fn parse(path: &str) -> Option<Pattern> {
+        let parts: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+        if !parts.iter().any(|part| part.starts_with([':', '*'])) {
+            return None;
+        }
+
+        let last = parts.len() - 1;
+        let segments = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| match part.split_at_checked(1) {
+                Some((":", name)) => {
+                    assert!(!name.is_empty(), "route {path} has a parameter without a name");
+                    Segment::Param(name.to_owned())
+                }
+                Some(("*", name)) => {
+                    assert!(i == last, "route {path} has a wildcard before its last segment");
+                    Segment::Wildcard(if name.is_empty() { "*" } else { name }.to_owned())
+                }
+                _ => Segment::Static((*part).to_owned()),
+            })
+            .collect();
+        Some(Pattern { segments })
+    }
+
+    /// The parameters' values if `path` matches.
+    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut parts = path.strip_prefix('/')?.split('/');
+        let mut params = HashMap::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Static(text) => {
+                    if parts.next()? != text {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let part = parts.next().filter(|part| !part.is_empty())?;
+                    params.insert(name.clone(), part.to_owned());
+                }
+                Segment::Wildcard(name) => {
+                    // At least the slash before the wildcard must be there; what follows may be empty.
+                    let rest: Vec<&str> = parts.by_ref().collect();
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    params.insert(name.clone(), rest.join("/"));
+                }
+            }
+        }
+        parts.next().is_none().then_some(params)
+    }
+
+    /// The kinds of the pattern's segments, which order matching patterns from best to worst.
+    fn rank(&self) -> Vec<u8> {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Static(_) => 0,
+                Segment::Param(_) => 1,
+                Segment::Wildcard(_) => 2,
+            })
+            .collect()
+    }
+}
+
+/// The handler in `handlers` for `method`: a `HEAD` without a route of its own goes to the `GET` one.
+fn handler_for<'a>(handlers: &'a MethodHandlers, method: &Method) -> Option<&'a Arc<dyn Handler>> {
+    match method {
+        Method::Head => handlers.get(&Method::Head).or_else(|| handlers.get(&Method::Get)),
+        method => handlers.get(method),
+    }
+}
+
+/// The response for a path that has routes, but not for the request's method.
+///
+/// `Allow` lists the path's methods in a fixed order, including `HEAD` wherever `GET` is routed.
+fn method_not_allowed(handlers: &MethodHandlers) -> Response {
+    let mut allowed: Vec<Method> = handlers.keys().cloned().collect();
+    if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+        allowed.push(Method::Head);
+        allowed.sort();
+    }
+
+    let allow: Vec<&str> = allowed.iter().map(Method::as_str).collect();
+    Response::with_status(405).header("Allow", &allow.join(", "))
+}
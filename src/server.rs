@@ -0,0 +1,1114 @@
+// The accept loop: hands each client connection to the thread pool and serves its requests.
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write}; // For buffered reading and writing
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, TcpListener, TcpStream, ToSocketAddrs}; // For TCP networking
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;             // For serving connections on Unix sockets
+#[cfg(any(unix, feature = "tls"))]
+use std::path::Path;                            // For socket, certificate and key locations
+#[cfg(unix)]
+use std::fs;                                    // For removing the socket file on shutdown
+use std::sync::Arc;                             // For sharing the router between workers
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering}; // For the shutdown flag and connection count
+use std::thread;                                // For pausing between accept attempts
+use std::time::{Duration, Instant, SystemTime}; // For socket timeouts, accept polling and access logs
+
+use crate::{Submitter, ThreadPool};
+use crate::access_log::{Entry, LogFormat};
+use crate::listener::{Connection, ListenAddr, Listener};
+use crate::metrics::Metrics;
+use crate::request::{self, BodyLength, Method, ParseError, Request, RequestLimits};
+use crate::request_id;
+use crate::response::Response;
+use crate::router::Router;
+use crate::websocket::WebSocket;
+#[cfg(feature = "tls")]
+use crate::tls;
+
+/// How long an idle keep-alive connection may hold a worker before it is closed, by default.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default limit on how long reading a request, or writing a response, may take.
+const DEFAULT_IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the accept loop checks the shutdown flag while no clients are connecting.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a connection closed on a client that may still be sending is drained for first.
+const LINGER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An HTTP server bound to a listening socket, either TCP or (on Unix) a Unix domain socket,
+/// or to an IPv4 and an IPv6 socket at once.
+///
+/// Binding and serving are separate steps, so the caller can find out which address
+/// was bound (useful with port 0) before the accept loop takes over.
+///
+/// Problems with the listener, connections and requests are reported through the [`log`] crate
+/// as warnings and errors. Each request's access log line is separate; see [`Server::log_format`].
+pub struct Server {
+    listeners: Vec<Listener>,         // The bound listening sockets
+    local_addrs: Vec<ListenAddr>,     // The addresses actually bound, with the real port, one per listener
+    router: Arc<Router>,              // Shared with every connection job
+    shutdown: Arc<AtomicBool>,        // Set to stop the accept loop
+    timeouts: Timeouts,               // Applied to every client socket
+    max_requests: Option<usize>,      // Requests served on one connection before it is closed (None = no limit)
+    request_limits: RequestLimits,    // How big each request read may be
+    nodelay: bool,                    // Set TCP_NODELAY on accepted connections
+    log_format: LogFormat,            // How each request is logged
+    server_header: Option<Arc<str>>,  // Sent as the Server header of every response (None = no header)
+    max_connections: Option<usize>,   // Connections served at once before new ones get 503 (None = no limit)
+    connections: Arc<AtomicUsize>,    // Connections accepted and not yet finished
+    shutdown_grace: Option<Duration>, // How long shutdown waits for connections to finish (None = as long as they take)
+    metrics: Arc<Metrics>,            // Request and pool counters, served on /metrics
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>, // Serve HTTPS with this config (None = plain HTTP)
+}
+
+/// The `Server` header sent by default: this crate's name and version.
+const DEFAULT_SERVER_HEADER: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The settings each connection is served with, handed to the worker serving it.
+#[derive(Debug, Clone)]
+struct ConnectionOptions {
+    timeouts: Timeouts,              // Applied to the client socket
+    max_requests: Option<usize>,     // Requests served before the connection is closed (None = no limit)
+    request_limits: RequestLimits,   // How big each request read may be
+    log_format: LogFormat,           // How each request is logged
+    server_header: Option<Arc<str>>, // Added to every response that doesn't set its own (None = no header)
+}
+
+/// Socket timeouts applied to each connection.
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    read: Duration,  // Waiting for, and reading, a request
+    write: Duration, // Writing a response
+    idle: Duration,  // Waiting for the next request on a keep-alive connection
+}
+
+impl Server {
+    /// Bind a listening socket to `addr`, e.g. `127.0.0.1:6969` or `[::]:6969`.
+    ///
+    /// Binding to port 0 picks a free port; use [`Server::local_addr`] to find out which.
+    /// Whether an IPv6 wildcard address also accepts IPv4 clients depends on the operating system;
+    /// use [`Server::bind_dual_stack`] to be sure of both.
+    /// The server starts with an empty router, so every request gets a 404 until
+    /// [`Server::router`] is called.
+    ///
+    /// # Errors
+    /// Returns an error if `addr` is not a valid socket address or can't be bound
+    /// (e.g. the port is already in use).
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = ListenAddr::Tcp(listener.local_addr()?);
+        Ok(Server::new(vec![Listener::Tcp(listener)], vec![local_addr]))
+    }
+
+    /// Like [`Server::bind`], but with room for `backlog` connections that have arrived and not
+    /// been accepted yet, instead of the standard library's default. A larger backlog lets
+    /// a burst of clients wait rather than have their connection attempts dropped.
+    ///
+    /// The operating system may cap the backlog, e.g. at `net.core.somaxconn` on Linux.
+    ///
+    /// # Errors
+    /// As for [`Server::bind`].
+    pub fn bind_with_backlog<A: ToSocketAddrs>(addr: A, backlog: u32) -> io::Result<Server> {
+        let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+        let listener = Listener::bind_tcp_with_backlog(addr, backlog)?;
+        let local_addr = ListenAddr::Tcp(listener.local_addr()?);
+        Ok(Server::new(vec![Listener::Tcp(listener)], vec![local_addr]))
+    }
+
+    /// Listen on `port` on every interface, over both IPv4 and IPv6.
+    ///
+    /// Binds `[::]` and then `0.0.0.0` on the same port, serving both from one accept loop.
+    /// On systems where the IPv6 socket already takes IPv4 clients as well, the second bind is
+    /// refused and the one socket serves both. Port 0 picks a free port shared by the two.
+    ///
+    /// # Errors
+    /// Returns an error if either address can't be bound (e.g. the port is already in use).
+    pub fn bind_dual_stack(port: u16) -> io::Result<Server> {
+        let v6 = TcpListener::bind((Ipv6Addr::UNSPECIFIED, port))?;
+        let v6_addr = v6.local_addr()?;
+        let mut listeners = vec![Listener::Tcp(v6)];
+        let mut local_addrs = vec![ListenAddr::Tcp(v6_addr)];
+
+        match TcpListener::bind((Ipv4Addr::UNSPECIFIED, v6_addr.port())) {
+            Ok(v4) => {
+                local_addrs.push(ListenAddr::Tcp(v4.local_addr()?));
+                listeners.push(Listener::Tcp(v4));
+            }
+            // The IPv6 socket holds the IPv4 port too, so it is already dual-stack.
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(Server::new(listeners, local_addrs))
+    }
+
+    /// Bind a Unix domain socket at `path`, e.g. for a reverse proxy on the same host.
+    ///
+    /// A socket file left behind by a server that didn't shut down cleanly is removed first,
+    /// and the socket file is removed again once [`Server::run`] returns.
+    /// Requests arriving over the socket have no client address.
+    ///
+    /// # Errors
+    /// Returns an error if another server is still listening at `path`, if something other than
+    /// a socket is already there, or if the socket can't be created (e.g. the directory is missing).
+    #[cfg(unix)]
+    pub fn bind_unix(path: impl AsRef<Path>) -> io::Result<Server> {
+        let path = path.as_ref();
+        let listener = Listener::bind_unix(path)?;
+        Ok(Server::new(vec![listener], vec![ListenAddr::Unix(path.to_owned())]))
+    }
+
+    /// A server with default settings accepting connections from `listeners`,
+    /// which are bound to the matching `local_addrs`.
+    fn new(listeners: Vec<Listener>, local_addrs: Vec<ListenAddr>) -> Server {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let metrics = Arc::new(Metrics::new());
+        let mut router = Router::new();
+        router.watch_shutdown(Arc::clone(&shutdown));
+        router.watch_metrics(Arc::clone(&metrics));
+        Server {
+            listeners,
+            local_addrs,
+            router: Arc::new(router),
+            shutdown,
+            timeouts: Timeouts {
+                read: DEFAULT_IO_TIMEOUT,
+                write: DEFAULT_IO_TIMEOUT,
+                idle: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            },
+            max_requests: None,
+            request_limits: RequestLimits::default(),
+            nodelay: true,
+            log_format: LogFormat::default(),
+            server_header: Some(Arc::from(DEFAULT_SERVER_HEADER)),
+            max_connections: None,
+            connections: Arc::new(AtomicUsize::new(0)),
+            shutdown_grace: None,
+            metrics,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Use `router` to answer requests.
+    ///
+    /// Its built-in `/healthz` check reports this server shutting down, and its built-in
+    /// `/metrics` reports this server's counters.
+    pub fn router<S>(mut self, router: Router<S>) -> Server {
+        let mut router = router.into_stateless();
+        router.watch_shutdown(Arc::clone(&self.shutdown));
+        router.watch_metrics(Arc::clone(&self.metrics));
+        self.router = Arc::new(router);
+        self
+    }
+
+    /// Limit how long a client may take to send its first request, or to finish sending any request
+    /// once it has started. Defaults to 30 seconds.
+    ///
+    /// A client that runs out of time gets 408 Request Timeout and is disconnected.
+    ///
+    /// # Panics
+    /// Panics if `timeout` is zero, which sockets don't accept.
+    pub fn read_timeout(mut self, timeout: Duration) -> Server {
+        assert!(!timeout.is_zero(), "read timeout must be non-zero");
+        self.timeouts.read = timeout;
+        self
+    }
+
+    /// Limit how long writing a response may block on a client that isn't reading it.
+    /// Defaults to 30 seconds; a write that runs out of time drops the connection.
+    ///
+    /// # Panics
+    /// Panics if `timeout` is zero, which sockets don't accept.
+    pub fn write_timeout(mut self, timeout: Duration) -> Server {
+        assert!(!timeout.is_zero(), "write timeout must be non-zero");
+        self.timeouts.write = timeout;
+        self
+    }
+
+    /// Limit how long a keep-alive connection may sit idle between requests before it is closed,
+    /// freeing its worker. Defaults to 5 seconds.
+    ///
+    /// # Panics
+    /// Panics if `timeout` is zero, which sockets don't accept.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Server {
+        assert!(!timeout.is_zero(), "keep-alive timeout must be non-zero");
+        self.timeouts.idle = timeout;
+        self
+    }
+
+    /// Close a connection once it has served `max` requests, so one client can't keep a worker
+    /// to itself forever. The last response tells the client with `Connection: close`, and it
+    /// reconnects for anything further. By default there is no limit.
+    ///
+    /// # Panics
+    /// Panics if `max` is zero.
+    pub fn max_requests_per_connection(mut self, max: usize) -> Server {
+        assert!(max > 0, "a connection must be allowed at least one request");
+        self.max_requests = Some(max);
+        self
+    }
+
+    /// Limit the size of each request's head and body, as [`Request::from_reader_with_limits`]
+    /// does. Defaults to [`RequestLimits::default`].
+    ///
+    /// A request with a bigger body gets 413 Payload Too Large, and one with longer or more
+    /// headers 431 Request Header Fields Too Large; either way the connection is closed.
+    pub fn request_limits(mut self, limits: RequestLimits) -> Server {
+        self.request_limits = limits;
+        self
+    }
+
+    /// Read request bodies of up to `max` bytes, such as uploads, JSON and forms, instead of 1 MiB.
+    /// Bigger ones get 413 Payload Too Large without being read. The other limits are kept;
+    /// see [`Server::request_limits`].
+    pub fn max_body_size(mut self, max: usize) -> Server {
+        self.request_limits.max_body_size = max;
+        self
+    }
+
+    /// Whether to set `TCP_NODELAY` on accepted connections, so that a small response goes out
+    /// as soon as it is written instead of being held back by Nagle's algorithm. Defaults to `true`.
+    pub fn nodelay(mut self, enabled: bool) -> Server {
+        self.nodelay = enabled;
+        self
+    }
+
+    /// Choose how each request is written to the access log on stdout, or turn it off.
+    /// Defaults to [`LogFormat::Plain`].
+    pub fn log_format(mut self, format: LogFormat) -> Server {
+        self.log_format = format;
+        self
+    }
+
+    /// Identify the server as `value` in the `Server` header of every response, or send no
+    /// `Server` header at all with `None`, so as not to tell clients what they are talking to.
+    /// Defaults to the crate's name and version, e.g. `rust_webserver/0.1.0`.
+    ///
+    /// A handler that sets its own `Server` header keeps it.
+    pub fn server_header(mut self, value: Option<&str>) -> Server {
+        self.server_header = value.map(Arc::from);
+        self
+    }
+
+    /// Serve at most `max` connections at once, counting those waiting in the pool's queue.
+    /// By default there is no limit.
+    ///
+    /// A client that connects while the server is full gets 503 Service Unavailable and is
+    /// disconnected straight away, so a flood of connections can't pile up in the queue.
+    pub fn max_connections(mut self, max: usize) -> Server {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Limit how long [`Server::run`] waits, once the shutdown flag is set, for the connections
+    /// already accepted to finish. By default it waits for as long as they take.
+    ///
+    /// Connections still open at the deadline are abandoned: they are logged, and `run` returns
+    /// without waiting for their workers, which end when the process exits. An idle keep-alive
+    /// connection counts as open until it times out, after up to [`Server::keep_alive_timeout`].
+    pub fn shutdown_grace(mut self, grace: Duration) -> Server {
+        self.shutdown_grace = Some(grace);
+        self
+    }
+
+    /// Serve HTTPS, using the PEM certificate chain at `cert_path` and the PEM private key at `key_path`.
+    ///
+    /// The files are read once, here. No ALPN protocols are advertised, which is fine for
+    /// HTTP/1.1 clients; use [`Server::tls_config`] to negotiate ALPN or tune anything else.
+    ///
+    /// # Errors
+    /// Returns an error if either file can't be read or parsed, or the key doesn't fit the certificate.
+    #[cfg(feature = "tls")]
+    pub fn tls(self, cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> io::Result<Server> {
+        let config = tls::load_config(cert_path.as_ref(), key_path.as_ref())?;
+        Ok(self.tls_config(Arc::new(config)))
+    }
+
+    /// Serve HTTPS with a ready-made rustls config.
+    ///
+    /// Setting `alpn_protocols` on it is optional. If it is set, it should include `http/1.1`,
+    /// the only protocol the server speaks.
+    #[cfg(feature = "tls")]
+    pub fn tls_config(mut self, config: Arc<rustls::ServerConfig>) -> Server {
+        self.tls = Some(config);
+        self
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> &ListenAddr {
+        &self.local_addrs[0]
+    }
+
+    /// Every address the server is listening on: two for [`Server::bind_dual_stack`]
+    /// on systems that need a separate IPv4 socket, otherwise just [`Server::local_addr`].
+    pub fn local_addrs(&self) -> &[ListenAddr] {
+        &self.local_addrs
+    }
+
+    /// A flag that stops [`Server::run`] when set to `true`, e.g. from a Ctrl-C handler.
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shutdown)
+    }
+
+    /// Accept connections until the shutdown flag is set, serving each one on a worker from `pool`.
+    ///
+    /// The listeners are polled without blocking so the flag is noticed within
+    /// `ACCEPT_POLL_INTERVAL`. Once it is, no new connections are accepted and `pool` is dropped,
+    /// which waits for every connection already handed to it to finish; an idle keep-alive
+    /// connection can delay this by up to [`Server::keep_alive_timeout`]. With a [`Server::shutdown_grace`],
+    /// connections still open when it runs out are abandoned instead.
+    /// To keep the pool, for other work or to reuse it afterwards, use [`Server::run_with`].
+    /// An accept that fails because of one connection, or because the process is out of file
+    /// descriptors or memory for now, is logged and skipped rather than stopping the server.
+    /// If a listener itself stops working, the server shuts down as if the flag had been set.
+    ///
+    /// # Errors
+    /// Returns an error if a listener can't be switched to non-blocking mode, or the error
+    /// of a listener that stopped working once the connections already accepted are done.
+    pub fn run(self, pool: ThreadPool) -> io::Result<()> {
+        let (grace, connections) = (self.shutdown_grace, Arc::clone(&self.connections));
+        let result = self.run_with(&pool);
+
+        // The connections have all finished, unless the grace period ran out first.
+        let remaining = connections.load(Ordering::SeqCst);
+        if let Some(grace) = grace
+            && remaining > 0
+        {
+            log::warn!("Shutdown grace period of {grace:?} ran out; abandoning {remaining} open connection(s)");
+            pool.abandon();
+        } else {
+            drop(pool);
+        }
+        result
+    }
+
+    /// Like [`Server::run`], but on a pool the caller keeps, e.g. one built with
+    /// a [`ThreadPoolBuilder`](crate::ThreadPoolBuilder) that also runs other jobs.
+    ///
+    /// Once the shutdown flag is set, this waits for the connections already accepted to finish,
+    /// for up to the [`Server::shutdown_grace`] if there is one. Connections still open after
+    /// that go on running on `pool`; dropping it afterwards waits for them.
+    ///
+    /// # Errors
+    /// As for [`Server::run`].
+    pub fn run_with(self, pool: &ThreadPool) -> io::Result<()> {
+        for listener in &self.listeners {
+            listener.set_nonblocking(true)?;
+        }
+        self.metrics.watch_pool(pool);
+
+        let mut failure = None;
+        while failure.is_none() && !self.shutdown.load(Ordering::SeqCst) {
+            let mut accepted = false;
+            for listener in &self.listeners {
+                match listener.accept() {
+                    Ok(stream) => {
+                        accepted = true;
+                        self.dispatch(stream, pool);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) if accept_error_is_fatal(&e) => {
+                        log::error!("Listener failed, shutting down: {e}");
+                        failure = Some(e);
+                        break;
+                    }
+                    // Nothing to hand to a worker; a full descriptor table gets another try after the pause below.
+                    Err(e) => log::warn!("Failed to accept connection: {e}"),
+                }
+            }
+
+            // Nobody is connecting; check the flag again shortly.
+            if !accepted {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+        }
+
+        // Let the connections already being served finish, unless the grace period runs out.
+        self.drain(self.shutdown_grace);
+
+        // Nothing will answer on the socket file any more, so don't leave it lying around.
+        #[cfg(unix)]
+        for addr in &self.local_addrs {
+            if let ListenAddr::Unix(path) = addr
+                && let Err(e) = fs::remove_file(path)
+            {
+                log::warn!("Failed to remove {}: {e}", path.display());
+            }
+        }
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Wait up to `grace` (`None` = as long as it takes) for every accepted connection to finish.
+    fn drain(&self, grace: Option<Duration>) {
+        let deadline = grace.map(|grace| Instant::now() + grace);
+        while self.connections.load(Ordering::SeqCst) > 0 {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return;
+            }
+            thread::sleep(ACCEPT_POLL_INTERVAL);
+        }
+    }
+
+    /// The settings a newly accepted connection is served with.
+    fn connection_options(&self) -> ConnectionOptions {
+        ConnectionOptions {
+            timeouts: self.timeouts,
+            max_requests: self.max_requests,
+            request_limits: self.request_limits,
+            log_format: self.log_format,
+            server_header: self.server_header.clone(),
+        }
+    }
+
+    /// Hand a newly accepted connection to `pool`, or turn it away if the server is full.
+    ///
+    /// A [`Response::delayed`] response is passed on to the pool's timer along with the connection,
+    /// so the worker goes back to serving other connections while the response waits (see `serve`).
+    fn dispatch(&self, stream: Connection, pool: &ThreadPool) {
+        // Turn the client away here rather than queueing it behind everyone else.
+        let Some(slot) = ConnectionSlot::acquire(&self.connections, self.max_connections) else {
+            reject_busy(stream, &self.connection_options(), &self.metrics);
+            return;
+        };
+        // Some platforms let accepted sockets inherit non-blocking mode; workers expect blocking reads.
+        if let Err(e) = stream.set_nonblocking(false) {
+            log::warn!("Failed to configure connection: {e}");
+            return;
+        }
+        // Responses are written in one go and flushed, so there is nothing to gain from batching.
+        // A connection that keeps Nagle's algorithm is only slower, so it is still served.
+        if self.nodelay
+            && let Err(e) = stream.set_nodelay(true)
+        {
+            log::warn!("Failed to set TCP_NODELAY: {e}");
+        }
+
+        let context = Context {
+            connection: Arc::new(stream),
+            router: Arc::clone(&self.router),
+            options: self.connection_options(),
+            metrics: Arc::clone(&self.metrics),
+            submitter: pool.submitter(),
+            _slot: slot,
+        };
+        #[cfg(feature = "tls")]
+        let tls = self.tls.clone();
+        // Submit the connection to the thread pool for processing.
+        // The pool only refuses jobs once it has shut down, in which case the connection is dropped.
+        // The slot is freed once the last job serving the connection is done with it, or dropped unrun.
+        if let Err(e) = pool.execute(move || {
+            let stream = SharedConnection(Arc::clone(&context.connection));
+            #[cfg(feature = "tls")]
+            if let Some(config) = tls {
+                serve_tls(stream, config, context);
+                return;
+            }
+            serve(ConnectionState::new(stream), context);
+        }) {
+            log::warn!("Failed to schedule connection: {e}");
+        }
+    }
+}
+
+/// One connection counted against [`Server::max_connections`], released when dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    /// Count one more connection, unless `max` are already being served.
+    fn acquire(connections: &Arc<AtomicUsize>, max: Option<usize>) -> Option<ConnectionSlot> {
+        let previous = connections.fetch_add(1, Ordering::SeqCst);
+        let slot = ConnectionSlot(Arc::clone(connections));
+        // Dropping the slot undoes the increment.
+        max.is_none_or(|max| previous < max).then_some(slot)
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Whether a failed accept means the listener itself is broken, rather than one connection
+/// (aborted or reset before it was accepted) or a shortage that will pass, such as running
+/// out of file descriptors, so that trying again can never succeed.
+fn accept_error_is_fatal(e: &io::Error) -> bool {
+    match e.kind() {
+        // The socket isn't listening (EINVAL), or can't accept connections at all (EOPNOTSUPP).
+        io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported => true,
+        // The descriptor was closed (EBADF, which is 9 on every Unix).
+        #[cfg(unix)]
+        _ if e.raw_os_error() == Some(9) => true,
+        _ => false,
+    }
+}
+
+/// Answers a connection the server has no room for with 503 Service Unavailable and closes it.
+///
+/// This runs on the accept loop, so the socket is made non-blocking: a client that isn't reading
+/// just misses the response instead of holding up everyone else's connections.
+/// Whatever part of the request has already arrived is read and discarded first, since closing
+/// a socket with unread data resets the connection and the client could lose the response.
+fn reject_busy(stream: Connection, options: &ConnectionOptions, metrics: &Metrics) {
+    let (time, started) = (SystemTime::now(), Instant::now());
+    let client = stream.socket().peer_ip();
+    let id = request_id::generate();
+    let response = Response::with_status(503).header("Connection", "close").header("X-Request-Id", &id);
+    let response = with_server_header(response, options);
+
+    let written = stream.set_nonblocking(true).and_then(|()| {
+        let mut discard = [0; 4096];
+        while matches!((&stream).read(&mut discard), Ok(n) if n > 0) {}
+        send(&response, false, &stream)?;
+        stream.shutdown(Shutdown::Write)
+    });
+    if let Err(e) = written {
+        report_send_error(&e);
+        return;
+    }
+
+    Entry {
+        time,
+        client,
+        method: "-",
+        path: "-",
+        status: response.status,
+        duration: started.elapsed(),
+        request_id: &id,
+    }
+    .log(options.log_format);
+    metrics.record(response.status, false);
+}
+
+/// The socket a connection's stream runs over, for the settings that streams don't expose.
+trait Socket {
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()>;
+    fn set_write_timeout(&self, timeout: Duration) -> io::Result<()>;
+    /// The client's address, if the socket still knows it.
+    fn peer_ip(&self) -> Option<IpAddr>;
+}
+
+impl Socket for TcpStream {
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, Some(timeout))
+    }
+
+    fn set_write_timeout(&self, timeout: Duration) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, Some(timeout))
+    }
+
+    /// IPv4 clients of a dual-stack IPv6 socket show up with their IPv4 address, not as `::ffff:a.b.c.d`.
+    fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_addr().ok().map(|addr| addr.ip().to_canonical())
+    }
+}
+
+#[cfg(unix)]
+impl Socket for UnixStream {
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, Some(timeout))
+    }
+
+    fn set_write_timeout(&self, timeout: Duration) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, Some(timeout))
+    }
+
+    /// Unix socket peers have no IP address.
+    fn peer_ip(&self) -> Option<IpAddr> {
+        None
+    }
+}
+
+impl Connection {
+    /// The socket underneath the connection.
+    fn socket(&self) -> &dyn Socket {
+        match self {
+            Connection::Tcp(stream) => stream,
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream,
+        }
+    }
+}
+
+/// A connection's stream, owned so that it can move from one job serving the connection to the
+/// next, and written through the `Connection` it shares with the job's [`Context`].
+struct SharedConnection(Arc<Connection>);
+
+impl Read for SharedConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self.0).read(buf)
+    }
+}
+
+impl Write for SharedConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self.0).flush()
+    }
+}
+
+/// A stream requests are read from and responses written to: the connection itself, or a TLS
+/// session on top of it.
+trait Transport: Read + Write + Send + 'static {
+    /// Wrap up once the last response has been sent.
+    fn close(&mut self) {}
+}
+
+impl Transport for SharedConnection {}
+
+#[cfg(feature = "tls")]
+impl Transport for rustls::StreamOwned<rustls::ServerConnection, SharedConnection> {
+    /// A close_notify lets the client tell a finished session from a cut-off one.
+    /// The client may well be gone by now, so failing to send it isn't worth reporting.
+    fn close(&mut self) {
+        self.conn.send_close_notify();
+        let _ = self.flush();
+    }
+}
+
+/// What every job serving a connection needs, handed from one to the next along with it.
+struct Context {
+    connection: Arc<Connection>, // The client's socket
+    router: Arc<Router>,         // Picks the handler for each request
+    options: ConnectionOptions,  // How the connection is served
+    metrics: Arc<Metrics>,       // Counts each response sent
+    submitter: Submitter,        // Hands delayed responses to the pool's timer
+    _slot: ConnectionSlot,       // Counts the connection against `max_connections` until dropped
+}
+
+/// A connection between two requests.
+struct ConnectionState<S> {
+    // The reader lasts as long as the connection, so what it has buffered past one request is
+    // the start of the next. Responses are written straight to the stream underneath it.
+    reader: BufReader<S>,
+    served: usize, // Requests answered so far
+    linger: bool,  // Whether the client may still be sending its last request
+}
+
+impl<S: Read> ConnectionState<S> {
+    /// A connection that has yet to send its first request.
+    fn new(stream: S) -> ConnectionState<S> {
+        ConnectionState { reader: BufReader::new(stream), served: 0, linger: false }
+    }
+}
+
+/// Serves a connection over TLS, then tells the client the session is over.
+///
+/// The handshake happens as the first request is read, so it falls under the same read timeout.
+#[cfg(feature = "tls")]
+fn serve_tls(stream: SharedConnection, config: Arc<rustls::ServerConfig>, context: Context) {
+    let session = match rustls::ServerConnection::new(config) {
+        Ok(session) => session,
+        Err(e) => {
+            log::warn!("Failed to start TLS session: {e}");
+            return;
+        }
+    };
+
+    serve(ConnectionState::new(rustls::StreamOwned::new(session, stream)), context);
+}
+
+/// Serves requests on a connection with `handle_connection` until it is done with, then closes it
+/// (see `close`).
+///
+/// A [`Response::delayed`] response is handed to the pool's timer along with the connection, so no
+/// worker waits for it. The job the timer starts once it is due sends the response, then carries
+/// on serving the connection from there, just as `handle_connection` would have.
+fn serve<S: Transport>(mut state: ConnectionState<S>, context: Context) {
+    let socket = Some(context.connection.socket());
+    let Some((delay, outgoing)) = handle_connection(&mut state, socket, &context.router, &context.options, &context.metrics)
+    else {
+        close(state, &context.connection);
+        return;
+    };
+
+    let submitter = context.submitter.clone();
+    let send_later = move || {
+        #[cfg(feature = "tracing")]
+        let entered = outgoing.span.clone().entered();
+        let open = outgoing.finish(&mut state, &context.router, &context.options, &context.metrics);
+        #[cfg(feature = "tracing")]
+        drop(entered);
+        if open {
+            serve(state, context);
+        } else {
+            close(state, &context.connection);
+        }
+    };
+    // A pool that has shut down drops the connection, like one it refuses to serve at all.
+    if let Err(e) = submitter.execute_after(delay, send_later) {
+        log::warn!("Failed to schedule delayed response: {e}");
+    }
+}
+
+/// Closes a connection `serve` is done with, after [`Transport::close`] has wrapped up the stream.
+///
+/// A client that may still be sending, because its last request couldn't be parsed or was
+/// answered before its body was read, first has the send side shut down and what it sends
+/// discarded, until it closes its side or `LINGER_TIMEOUT` is up. Closing a socket with unread
+/// data resets the connection, which can destroy the response before the client has read it.
+fn close<S: Transport>(mut state: ConnectionState<S>, connection: &Connection) {
+    state.reader.get_mut().close();
+    if !state.linger || connection.shutdown(Shutdown::Write).is_err() {
+        return;
+    }
+
+    let deadline = Instant::now() + LINGER_TIMEOUT;
+    let mut discard = [0; 4096];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || connection.socket().set_read_timeout(left).is_err() {
+            return;
+        }
+        if !matches!((&*connection).read(&mut discard), Ok(n) if n > 0) {
+            return;
+        }
+    }
+}
+
+/// Handles an individual client connection by reading HTTP requests and sending an appropriate response to each.
+///
+/// # Arguments
+/// * `state` - The connection's stream, which requests are read from and responses written to,
+///   and how many requests it has already been answered.
+/// * `socket` - The socket underneath the stream, used for timeouts and the client's address.
+///   `None` for streams that aren't sockets, such as an in-memory buffer; those get no
+///   timeouts and are logged without a client address.
+/// * `router` - The routing table used to pick a handler for the request.
+/// * `options` - How long reads and writes on the stream may block and how long it may sit idle,
+///   how many requests it may serve, how they are logged, and the `Server` header to send.
+/// * `metrics` - The counters each response sent is added to.
+///
+/// Builds each response with `respond` and writes it back to the client. The connection is kept
+/// open for further requests until the client or the response asks to close it, or the client
+/// disconnects, sends a bad request, stays idle for longer than `options.timeouts.idle` between
+/// requests, or has sent `options.max_requests`. Pipelined requests, sent before the responses to earlier ones
+/// have arrived, are answered one at a time in the order they were sent.
+/// A client that never sends its first request or stalls mid-request gets 408 Request Timeout.
+/// Every response carries the request's id in `X-Request-Id`, and is logged with it and
+/// counted once it has been written.
+/// After a `101 Switching Protocols` the connection belongs to the route's WebSocket handler,
+/// and is closed once it returns.
+///
+/// A [`Response::delayed`] response isn't written here: it is returned with its delay, for the
+/// caller to send with [`Outgoing::finish`] once the delay has passed, then call this again with
+/// the same `state` if the connection is still open.
+///
+/// With the `tracing` feature, each request runs in a `request` span (see `request_span`),
+/// which gets the response's status and the time taken once the response has been written.
+fn handle_connection<S: Read + Write>(
+    state: &mut ConnectionState<S>,
+    socket: Option<&dyn Socket>,
+    router: &Router,
+    options: &ConnectionOptions,
+    metrics: &Metrics,
+) -> Option<(Duration, Outgoing)> {
+    // Don't let a slow client pin this worker forever.
+    if let Some(socket) = socket
+        && let Err(e) = socket.set_write_timeout(options.timeouts.write)
+    {
+        log::warn!("Failed to set write timeout: {e}");
+        return None;
+    }
+
+    let client = socket.and_then(Socket::peer_ip);
+
+    loop {
+        // Wait for the next request: the full read timeout for the first one,
+        // the shorter keep-alive timeout once the client has been served.
+        let first = state.served == 0;
+        let idle_timeout = if first { options.timeouts.read } else { options.timeouts.idle };
+        if let Some(socket) = socket
+            && let Err(e) = socket.set_read_timeout(idle_timeout)
+        {
+            log::warn!("Failed to set read timeout: {e}");
+            return None;
+        }
+
+        let ready = state.reader.fill_buf().map(|buf| !buf.is_empty());
+        // Time the request from its first byte, not from when we started waiting for it.
+        let (time, started) = (SystemTime::now(), Instant::now());
+        // Only something that will be answered gets a span, not the connection closing.
+        #[cfg(feature = "tracing")]
+        let span = match &ready {
+            Ok(true) => request_span(),
+            Err(e) if first && request::is_timeout(e) => request_span(),
+            _ => tracing::Span::none(),
+        };
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let (response, request) = match ready {
+            // The client has started sending a request; give it the read timeout to finish.
+            Ok(true) => match socket.map_or(Ok(()), |socket| socket.set_read_timeout(options.timeouts.read)) {
+                Ok(()) => respond(&mut state.reader, router, client, &options.request_limits),
+                Err(e) => {
+                    log::warn!("Failed to set read timeout: {e}");
+                    return None;
+                }
+            },
+            // A client that connected but never sent anything is told why it's being dropped.
+            Err(e) if first && request::is_timeout(&e) => (Response::with_status(408), None),
+            // Otherwise stop quietly: the client closed the connection or went idle between requests.
+            _ => break,
+        };
+        state.served += 1;
+
+        // HTTP/1.0 clients don't know chunked encoding, and assume the connection closes
+        // unless told otherwise.
+        let http_1_0 = request.as_ref().is_some_and(Request::is_http_1_0);
+        let response = if http_1_0 { response.unchunked() } else { response };
+
+        // A 101 hands the connection over to a WebSocket handler once the response is written.
+        // A body without a length, such as an event stream, only ends when the connection does.
+        // The last request a connection is allowed gets its answer with `Connection: close`,
+        // and a response that already says `Connection: close` closes the connection after it.
+        let upgraded = response.status == 101;
+        let exhausted = options.max_requests.is_some_and(|max| state.served >= max);
+        let closing = response.header_value("Connection").is_some_and(|value| value.eq_ignore_ascii_case("close"));
+        // A request that couldn't be parsed, or was answered with `Connection: close` before its
+        // body was read, may have left more of itself to come.
+        state.linger = request.is_none() || closing;
+        let keep_alive = upgraded
+            || (!exhausted
+                && !closing
+                && !response.is_close_delimited()
+                && request.as_ref().is_some_and(Request::keep_alive));
+        let head_only = request.as_ref().is_some_and(|r| r.method == Method::Head);
+        let response = match (keep_alive, http_1_0) {
+            (false, _) => response.set_header("Connection", "close"),
+            (true, true) if !upgraded => response.set_header("Connection", "keep-alive"),
+            (true, _) => response,
+        };
+
+        // A request that couldn't be parsed still gets an id, so its log line can be found.
+        let id = request.as_ref().and_then(|r| r.id.clone()).unwrap_or_else(request_id::generate);
+        let response = with_server_header(response.set_header("X-Request-Id", &id), options);
+        #[cfg(feature = "tracing")]
+        if request.is_none() {
+            span.record("request_id", id.as_str());
+        }
+
+        let outgoing = Outgoing {
+            response,
+            request,
+            head_only,
+            id,
+            client,
+            time,
+            started,
+            keep_alive,
+            upgraded,
+            #[cfg(feature = "tracing")]
+            span: span.clone(),
+        };
+        if let Some(delay) = outgoing.response.delay() {
+            return Some((delay, outgoing));
+        }
+        if !outgoing.finish(state, router, options, metrics) {
+            break;
+        }
+    }
+    None
+}
+
+/// A response ready to be written, with what its log line and metrics need once it has been.
+struct Outgoing {
+    response: Response,        // What to send
+    request: Option<Request>,  // What it answers (None = a request that couldn't be parsed)
+    head_only: bool,           // Whether to leave the body out, for HEAD
+    id: String,                // The request's id, also sent in `X-Request-Id`
+    client: Option<IpAddr>,    // Who asked, if known
+    time: SystemTime,          // When the request started arriving, for the log
+    started: Instant,          // The same moment, for timing the request
+    keep_alive: bool,          // Whether the connection stays open for another request
+    upgraded: bool,            // Whether a WebSocket handler takes the connection over afterwards
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,       // The request's span
+}
+
+impl Outgoing {
+    /// Write the response, flushed so the client sees it before the next request is read. After a
+    /// `101`, hand the connection to the route's WebSocket handler.
+    ///
+    /// Returns whether the connection is ready for another request: `false` once the client is
+    /// gone (the write failed), after a WebSocket, or if the response closes the connection.
+    fn finish<S: Read + Write>(
+        self,
+        state: &mut ConnectionState<S>,
+        router: &Router,
+        options: &ConnectionOptions,
+        metrics: &Metrics,
+    ) -> bool {
+        if !self.deliver(state.reader.get_mut(), options, metrics) {
+            return false;
+        }
+
+        if self.upgraded {
+            if let Some(request) = &self.request
+                && let Some(handler) = router.websocket_handler(&request.path)
+            {
+                let mut socket = WebSocket::new(&mut state.reader);
+                handler(request, &mut socket);
+                socket.finish();
+            }
+            return false;
+        }
+        self.keep_alive
+    }
+
+    /// Write the response to `stream` (see `send`), then log and count it. A response that
+    /// can't be written is reported instead, and `false` returned.
+    fn deliver<W: Write>(&self, stream: W, options: &ConnectionOptions, metrics: &Metrics) -> bool {
+        if let Err(e) = send(&self.response, self.head_only, stream) {
+            report_send_error(&e);
+            return false;
+        }
+
+        let request = self.request.as_ref();
+        Entry {
+            time: self.time,
+            client: self.client,
+            method: request.map_or("-", |r| r.method.as_str()),
+            path: request.map_or("-", |r| r.path.as_str()),
+            status: self.response.status,
+            duration: self.started.elapsed(),
+            request_id: &self.id,
+        }
+        .log(options.log_format);
+        metrics.record(self.response.status, request.is_some());
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("status", self.response.status);
+            self.span.record("elapsed_ms", self.started.elapsed().as_secs_f64() * 1000.0);
+            tracing::info!("response sent");
+        }
+        true
+    }
+}
+
+/// A new `request` span, with its fields still empty.
+///
+/// The request's `request_id`, `method` and `path` are recorded once its head has been read, and
+/// its `status` and `elapsed_ms` once the response has been written; a request that can't be
+/// parsed only gets its `request_id` along with the status, and keeps the method and path empty.
+#[cfg(feature = "tracing")]
+fn request_span() -> tracing::Span {
+    use tracing::field::Empty;
+    tracing::info_span!("request", request_id = Empty, method = Empty, path = Empty, status = Empty, elapsed_ms = Empty)
+}
+
+/// Writes `response` to `stream` through a buffer, flushed once at the end, so the head and
+/// a small body go out together instead of a write per header; `head_only` leaves the body out.
+///
+/// Whatever is still buffered when a write fails is thrown away, rather than tried again
+/// (and timed out again) when the buffer is dropped.
+fn send<W: Write>(response: &Response, head_only: bool, stream: W) -> io::Result<()> {
+    let mut writer = BufWriter::new(stream);
+    let written = if head_only { response.write_head_to(&mut writer) } else { response.write_to(&mut writer) };
+    let written = written.and_then(|()| writer.flush());
+    if written.is_err() {
+        drop(writer.into_parts());
+    }
+    written
+}
+
+/// Reads one request from `stream` and returns the response to send for it, along with
+/// the request itself if it could be parsed.
+///
+/// Parses the HTTP request line, headers, and body within `limits` (see `read_request`), tags the request with the `client` address
+/// and its id (see `request_id::for_request`), and passes it to the router. The response becomes a 304 if the client's copy is still fresh,
+/// is cut to the requested byte range, if any, and is gzipped when the client allows it.
+/// A request that is malformed or cut short gets 400 Bad Request with an empty body,
+/// and one whose body is too large gets 413 Payload Too Large. Either way the connection
+/// is closed, since the rest of the stream can no longer be trusted.
+fn respond<S: Read + Write>(
+    stream: &mut BufReader<S>,
+    router: &Router,
+    client: Option<IpAddr>,
+    limits: &RequestLimits,
+) -> (Response, Option<Request>) {
+    // Read the request line and headers.
+    // Example request line: "GET / HTTP/1.1"
+    // A client that disconnects early or sends garbage gets a 400 instead of crashing the worker.
+    match read_request(stream, router, limits) {
+        // Let the router pick the handler for this method and path.
+        // Compress the handler's response if the client accepts gzip.
+        Ok((mut request, body_read)) => {
+            request.client = client;
+            request.id = Some(request_id::for_request(&request));
+            #[cfg(feature = "tracing")]
+            {
+                let span = tracing::Span::current();
+                span.record("request_id", request.id.as_deref());
+                span.record("method", request.method.as_str());
+                span.record("path", request.path.as_str());
+                tracing::debug!("request received");
+            }
+            let response = router.dispatch(&request);
+            // A body the client was told not to send can't be told apart from a next request.
+            let response = if body_read { response } else { response.set_header("Connection", "close") };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(status = response.status, "handler finished");
+            let response = response
+                .conditional_for(&request)
+                .ranged_for(&request)
+                .compressed_for(&request);
+            (response, Some(request))
+        }
+        Err(e) => {
+            log::warn!("Rejecting bad request: {e}");
+            (Response::with_status(e.status()), None)
+        }
+    }
+}
+
+/// Adds the configured `Server` header to `response`, unless it has one of its own.
+fn with_server_header(response: Response, options: &ConnectionOptions) -> Response {
+    match &options.server_header {
+        Some(value) if response.header_value("Server").is_none() => response.header("Server", value),
+        _ => response,
+    }
+}
+
+/// Reports a response that couldn't be written. A client that hung up before reading it, such as
+/// a browser navigating away mid-download, is an everyday event, so that only gets a debug message.
+fn report_send_error(e: &io::Error) {
+    match e.kind() {
+        io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted => {
+            log::debug!("Client disconnected before the response was sent: {e}");
+        }
+        _ => log::warn!("Failed to send response: {e}"),
+    }
+}
+
+/// Reads a request's line, headers and body from `stream`, within `limits`. Also returns whether
+/// the body was read: it is left unsent by a client that `router` turns down before it arrives.
+///
+/// A client that sent `Expect: 100-continue` is waiting to hear whether to send the body, so it is
+/// told `100 Continue` once the headers have been read, unless the body would be too large, or the
+/// router would refuse the request whatever its body (see `Router::refuses`, e.g. a 404 or 405):
+/// then the final response is the answer, and the body is never sent. The interim response is
+/// flushed straight away.
+fn read_request<S: Read + Write>(
+    stream: &mut BufReader<S>,
+    router: &Router,
+    limits: &RequestLimits,
+) -> Result<(Request, bool), ParseError> {
+    let mut request = Request::read_head(stream, limits)?;
+    if request.expects_continue() && request.body_length(limits)? != BodyLength::Fixed(0) {
+        if router.refuses(&request) {
+            return Ok((request, false));
+        }
+        let writer = stream.get_mut();
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        writer.flush()?;
+    }
+    request.read_body(stream, limits)?;
+    Ok((request, true))
+}
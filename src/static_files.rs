@@ -0,0 +1,65 @@
+// Serving files from a directory on disk by URL path.
+use std::fs;                    // For resolving paths
+use std::io;                    // For telling missing files apart from other errors
+use std::path::{Path, PathBuf}; // For building file paths
+
+use crate::response::Response;
+
+/// A directory whose files are served by URL path, e.g. `/css/site.css` from `public/css/site.css`.
+#[derive(Debug, Clone)]
+pub struct StaticDir {
+    root: PathBuf, // The directory files are served from
+}
+
+impl StaticDir {
+    /// Serve the files under `root`. The directory doesn't need to exist yet.
+    pub fn new(root: impl Into<PathBuf>) -> StaticDir {
+        StaticDir { root: root.into() }
+    }
+
+    /// Look up the file for a URL path such as `/foo/bar.css`, already percent-decoded
+    /// like [`Request::path`](crate::Request::path).
+    ///
+    /// The path is resolved under the root. A directory is served through its `index.html`.
+    /// Returns `None` if there's no such file, so the caller can fall back to its own 404,
+    /// and `403 Forbidden` for paths that contain `..` or that resolve (e.g. through a symlink)
+    /// to somewhere outside the root. Since the path is decoded, an encoded `%2e%2e` or `%2F`
+    /// is caught as well.
+    pub fn serve(&self, relative: &str) -> Option<Response> {
+        // Refuse to walk upwards before touching the filesystem at all.
+        // Backslashes count as separators too, since they are on Windows.
+        if relative.split(['/', '\\']).any(|segment| segment == "..") || relative.contains('\0') {
+            return Some(Response::with_status(403));
+        }
+
+        let candidate = self.root.join(relative.trim_start_matches(['/', '\\']));
+        let (root, mut path) = match (fs::canonicalize(&self.root), fs::canonicalize(&candidate)) {
+            (Ok(root), Ok(path)) => (root, path),
+            _ => return None,
+        };
+
+        // Symlinks may still point outside the root, so check where the path really ended up.
+        if !path.starts_with(&root) {
+            return Some(Response::with_status(403));
+        }
+        if path.is_dir() {
+            path.push("index.html");
+        }
+
+        read_file(&path)
+    }
+}
+
+/// Open `path` as a `200 OK` response with a matching `Content-Type`; see [`Response::from_file`].
+///
+/// Returns `None` if the file doesn't exist and `500 Internal Server Error` if it can't be read.
+fn read_file(path: &Path) -> Option<Response> {
+    match Response::from_path(200, path) {
+        Ok(response) => Some(response),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::warn!("Failed to read {}: {e}", path.display());
+            Some(Response::with_status(500))
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// A minimal HTTP client for testing a running server without writing raw requests by hand.
+use std::io::{self, BufReader, Read, Write}; // For talking to the server
+use std::net::{SocketAddr, TcpStream};      // For connecting to it
+use std::time::Duration;                    // For not hanging on a server that never answers
+
+use crate::request::{self, Method, RequestLimits};
+
+/// How long a [`TestClient`] waits on the server before giving up, by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends requests to a [`Server`](crate::Server) over TCP and reads back the whole response,
+/// for integration tests. Needs the `testutil` feature.
+///
+/// Bind the server to port 0, pass its [`Server::local_addr`](crate::Server::local_addr) to
+/// [`TestClient::new`], run it on a thread of its own, then make requests such as
+/// `client.get("/")?` and check the returned [`TestResponse`].
+///
+/// Every request goes out on a new connection with `Connection: close`, so each response is
+/// read to the end of the stream, and requests never affect each other.
+#[derive(Debug, Clone)]
+pub struct TestClient {
+    addr: SocketAddr,  // Where the server is listening
+    timeout: Duration, // Applied to connecting, writing and reading
+}
+
+/// A response read by a [`TestClient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResponse {
+    /// The status code, e.g. `200`.
+    pub status: u16,
+    /// The headers in the order they were sent, with names as the server wrote them.
+    pub headers: Vec<(String, String)>,
+    /// The body, put back together if it was sent in chunks.
+    pub body: Vec<u8>,
+}
+
+impl TestClient {
+    /// A client for the server listening on `addr`.
+    pub fn new(addr: SocketAddr) -> TestClient {
+        TestClient { addr, timeout: DEFAULT_TIMEOUT }
+    }
+
+    /// Give up on the server after `timeout` instead of 10 seconds.
+    ///
+    /// # Panics
+    /// Panics if `timeout` is zero, which sockets don't accept.
+    pub fn timeout(mut self, timeout: Duration) -> TestClient {
+        assert!(!timeout.is_zero(), "timeout must be non-zero");
+        self.timeout = timeout;
+        self
+    }
+
+    /// `GET path`.
+    ///
+    /// # Errors
+    /// See [`TestClient::request`].
+    pub fn get(&self, path: &str) -> io::Result<TestResponse> {
+        self.request(Method::Get, path, &[], &[])
+    }
+
+    /// `POST path` with `body`.
+    ///
+    /// # Errors
+    /// See [`TestClient::request`].
+    pub fn post(&self, path: &str, body: impl AsRef<[u8]>) -> io::Result<TestResponse> {
+        self.request(Method::Post, path, &[], body.as_ref())
+    }
+
+    /// Send a request with `method`, `path`, extra `headers` and `body`, and read the response.
+    ///
+    /// `Host`, `Connection: close` and, for a non-empty body, `Content-Length` are added.
+    /// `path` goes on the request line as given, so percent-encode it first if need be.
+    ///
+    /// # Errors
+    /// Returns an error if the server can't be reached, doesn't answer within the timeout, or
+    /// sends something that isn't an HTTP/1.1 response (`InvalidData`).
+    pub fn request(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<TestResponse> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut head = format!("{method} {path} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n", self.addr);
+        for (name, value) in headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !body.is_empty() {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        // The server closes the connection after the response, so everything up to there is ours.
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        parse_response(&raw)
+    }
+}
+
+impl TestResponse {
+    /// Look up a header value by name, ignoring case. If the header repeats, this is the first value.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body as text, with invalid UTF-8 replaced by `U+FFFD`.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Split a complete response into its status, headers and body, undoing chunked encoding.
+fn parse_response(raw: &[u8]) -> io::Result<TestResponse> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_owned());
+
+    let split = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("response has no end of headers"))?;
+    let head = std::str::from_utf8(&raw[..split]).map_err(|_| invalid("response head is not UTF-8"))?;
+    let body = &raw[split + 4..];
+
+    // The status line looks like "HTTP/1.1 200 OK".
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.strip_prefix("HTTP/1.1 "))
+        .and_then(|rest| rest.split(' ').next())
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("bad status line"))?;
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("bad header line"))?;
+        headers.push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+
+    let mut response = TestResponse { status, headers, body: body.to_vec() };
+    if response.header("Transfer-Encoding").is_some_and(|coding| coding.eq_ignore_ascii_case("chunked")) {
+        let limits = RequestLimits { max_body_size: usize::MAX, ..RequestLimits::default() };
+        response.body = request::read_chunked(&mut BufReader::new(body), &limits)
+            .map_err(|e| invalid(&format!("bad chunked body: {e}")))?;
+    }
+    Ok(response)
+}
@@ -0,0 +1,147 @@
+// Timer thread that holds delayed jobs until they are due, then hands them to the pool.
+use std::cmp::Ordering;                            // For ordering entries by deadline
+use std::collections::BinaryHeap;                  // Priority queue of scheduled jobs
+use std::io;                                       // For spawn errors
+use std::sync::{Arc, Condvar, Mutex, PoisonError}; // Shared state between the pool and the timer thread
+use std::thread;                                   // For spawning the timer thread
+use std::time::{Duration, Instant};                // For computing deadlines
+use super::{Job, Workers};
+
+/// How far off the deadline of a job whose delay can't be added to the current time is:
+/// a century, which is as good as never.
+const FAR_FUTURE: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// A dedicated thread that dispatches delayed jobs into the pool's job queue.
+///
+/// Jobs are kept in a min-heap ordered by deadline, so the thread only ever
+/// sleeps until the earliest one is due.
+pub(crate) struct Timer {
+    shared: Arc<Shared>,            // Schedule shared with the timer thread
+    thread: thread::JoinHandle<()>, // Handle to the timer thread
+}
+
+/// State shared between the pool and the timer thread.
+struct Shared {
+    schedule: Mutex<Schedule>, // Pending jobs and the shutdown flag
+    changed: Condvar,          // Signalled when a job is added or the timer is stopped
+}
+
+struct Schedule {
+    entries: BinaryHeap<Entry>, // Jobs waiting for their deadline
+    next_seq: u64,              // Tie-breaker so jobs with equal deadlines keep submission order
+    stopped: bool,              // Set when the pool shuts down
+}
+
+/// A job waiting in the timer, ordered so the earliest deadline is at the top of the heap.
+struct Entry {
+    deadline: Instant,
+    seq: u64,
+    job: Job,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        // BinaryHeap is a max-heap, so reverse the comparison to pop the earliest deadline first.
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+impl Timer {
+    /// Spawn the timer thread.
+    ///
+    /// # Arguments
+    /// * `workers` - The pool's workers, whose queue jobs are dispatched to once they are due.
+    pub(crate) fn start(workers: Arc<Workers>) -> io::Result<Timer> {
+        let shared = Arc::new(Shared {
+            schedule: Mutex::new(Schedule {
+                entries: BinaryHeap::new(),
+                next_seq: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let thread_shared = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("timer".to_owned())
+            .spawn(move || run(&thread_shared, &workers))?;
+
+        Ok(Timer { shared, thread })
+    }
+
+    /// Schedule `job` to be dispatched once `delay` has elapsed.
+    ///
+    /// A delay too long for an [`Instant`] to represent, such as [`Duration::MAX`], is cut to
+    /// `FAR_FUTURE`, so the job waits until the pool shuts down.
+    pub(crate) fn schedule(&self, delay: Duration, job: Job) {
+        let now = Instant::now();
+        let deadline = now.checked_add(delay).unwrap_or_else(|| now + FAR_FUTURE);
+
+        let mut schedule = self.shared.schedule.lock().unwrap();
+        let seq = schedule.next_seq;
+        schedule.next_seq += 1;
+        schedule.entries.push(Entry { deadline, seq, job });
+
+        // Wake the timer thread in case this job is due before the one it is waiting on.
+        self.shared.changed.notify_one();
+    }
+
+    /// Stop the timer thread, discarding any jobs that are not yet due.
+    pub(crate) fn stop(self) {
+        self.shared.schedule.lock().unwrap_or_else(PoisonError::into_inner).stopped = true;
+        self.shared.changed.notify_one();
+        // A panic on the timer thread has already been printed; shutting down carries on regardless.
+        if self.thread.join().is_err() {
+            log::error!("The timer thread panicked");
+        }
+    }
+}
+
+/// Body of the timer thread: sleep until the earliest deadline, dispatch, repeat.
+fn run(shared: &Shared, workers: &Arc<Workers>) {
+    let mut schedule = shared.schedule.lock().unwrap();
+
+    loop {
+        if schedule.stopped {
+            if !schedule.entries.is_empty() {
+                log::info!("Timer stopped, discarding {} delayed job(s).", schedule.entries.len());
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        match schedule.entries.peek() {
+            // Nothing scheduled: wait until a job is added.
+            None => schedule = shared.changed.wait(schedule).unwrap(),
+            // The earliest job is due: release the lock while sending, since a
+            // bounded queue may block until a worker frees a slot.
+            Some(entry) if entry.deadline <= now => {
+                let entry = schedule.entries.pop().unwrap();
+                drop(schedule);
+                if workers.dispatch(0, entry.job).is_err() {
+                    return; // The queue is closed, so nothing else can run either.
+                }
+                schedule = shared.schedule.lock().unwrap();
+            }
+            // Sleep until the earliest job is due, or until the schedule changes.
+            Some(entry) => {
+                let timeout = entry.deadline - now;
+                schedule = shared.changed.wait_timeout(schedule, timeout).unwrap().0;
+            }
+        }
+    }
+}
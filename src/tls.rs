@@ -0,0 +1,45 @@
+// Loading the certificate and key that HTTPS connections are served with.
+use std::io;         // For reporting unreadable or invalid files
+use std::path::Path; // For the certificate and key locations
+use std::sync::Arc;  // For sharing the crypto provider
+
+use rustls::ServerConfig;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+/// Build a TLS server config from a PEM certificate chain and a PEM private key.
+///
+/// No ALPN protocols are advertised, so clients that offer ALPN simply don't get one
+/// negotiated and carry on with HTTP/1.1.
+///
+/// # Errors
+/// Returns an error if either file can't be read, contains no usable certificate or key,
+/// or the key doesn't match the certificate.
+pub(crate) fn load_config(cert_path: &Path, key_path: &Path) -> io::Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(cert_path, e))?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificates in {}", cert_path.display()),
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| pem_error(key_path, e))?;
+
+    // Ask for ring explicitly rather than relying on a process-wide default provider.
+    ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Turn a PEM error for the file at `path` into an io error, keeping the kind of an underlying io error.
+fn pem_error(path: &Path, e: pem::Error) -> io::Error {
+    let kind = match &e {
+        pem::Error::Io(e) => e.kind(),
+        _ => io::ErrorKind::InvalidData,
+    };
+    io::Error::new(kind, format!("{}: {e}", path.display()))
+}
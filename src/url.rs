@@ -0,0 +1,54 @@
+// Percent-decoding and query-string parsing shared by the request parsers.
+use std::collections::HashMap; // For decoded key/value pairs
+
+/// Decode `%XX` escapes in `input`.
+///
+/// When `plus_as_space` is set, `+` also decodes to a space, as in query strings and
+/// `application/x-www-form-urlencoded` bodies.
+/// Returns `None` if an escape is not followed by two hex digits or the decoded bytes
+/// are not valid UTF-8.
+pub(crate) fn percent_decode(input: &str, plus_as_space: bool) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3)?;
+                let hex = std::str::from_utf8(hex).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Parse `a=1&b=2` style pairs, percent-decoding keys and values with `+` as a space.
+///
+/// - A key without `=` gets an empty value, as does `key=`.
+/// - When a key repeats, the first value is kept.
+/// - Empty pairs (e.g. from `a=1&&b=2`) are skipped.
+/// - A key or value with an invalid escape is kept exactly as sent.
+pub(crate) fn parse_query(query: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let key = percent_decode(key, true).unwrap_or_else(|| key.to_owned());
+        let value = percent_decode(value, true).unwrap_or_else(|| value.to_owned());
+        params.entry(key).or_insert(value);
+    }
+
+    params
+}
@@ -0,0 +1,305 @@
+// The WebSocket opening handshake and a minimal frame reader and writer for text messages.
+use std::io::{self, BufReader, Read, Write}; // For the connection underneath a WebSocket
+
+use crate::base64;
+use crate::request::{Method, Request};
+use crate::response::Response;
+
+/// Appended to the client's key before hashing, as fixed by RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only protocol version there is.
+const VERSION: &str = "13";
+
+/// Messages bigger than this are refused, so a client can't make the server buffer without bound.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Close status codes from RFC 6455.
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+/// A connection that has been upgraded to the WebSocket protocol;
+/// see [`Router::websocket`](crate::Router::websocket).
+///
+/// Only text messages are supported: a binary message from the client closes the connection.
+/// Pings are answered automatically. Reads are subject to the server's read timeout, so a
+/// client that stays silent longer than that makes [`WebSocket::recv`] fail with a timeout error.
+pub struct WebSocket<'a> {
+    stream: &'a mut dyn Stream, // The connection, with anything already buffered from it
+    closing: bool,              // We have sent a close frame
+    closed: bool,               // The client has sent a close frame
+}
+
+/// A connection that can be read from, through its buffer, and written to.
+pub(crate) trait Stream {
+    fn reader(&mut self) -> &mut dyn Read;
+    fn writer(&mut self) -> &mut dyn Write;
+}
+
+impl<S: Read + Write> Stream for BufReader<S> {
+    fn reader(&mut self) -> &mut dyn Read {
+        self
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        self.get_mut()
+    }
+}
+
+impl<'a> WebSocket<'a> {
+    /// Speak the WebSocket protocol over `stream`, once the `101` response has been sent.
+    pub(crate) fn new(stream: &'a mut dyn Stream) -> WebSocket<'a> {
+        WebSocket { stream, closing: false, closed: false }
+    }
+
+    /// Wait for the next text message from the client.
+    ///
+    /// Returns `Ok(None)` once the client has closed the connection; the close is acknowledged
+    /// before returning.
+    ///
+    /// # Errors
+    /// Returns any io error raised while reading, including a timeout. A frame that breaks the
+    /// protocol, a binary message, text that isn't UTF-8 or a message over `MAX_MESSAGE_SIZE`
+    /// gives an `InvalidData` error, after telling the client why the connection is being closed.
+    pub fn recv(&mut self) -> io::Result<Option<String>> {
+        let mut message = Vec::new();
+        let mut in_message = false;
+        while !self.closed {
+            let (fin, opcode, payload) = self.read_frame()?;
+            match opcode {
+                OP_TEXT if !in_message => in_message = true,
+                OP_CONTINUATION if in_message => {}
+                OP_PING => {
+                    self.write_frame(OP_PONG, &payload)?;
+                    continue;
+                }
+                OP_PONG => continue,
+                OP_CLOSE => {
+                    self.closed = true;
+                    if !self.closing {
+                        // Echo the status code, if any, as the acknowledgement.
+                        self.closing = true;
+                        self.write_frame(OP_CLOSE, &payload[..payload.len().min(2)])?;
+                    }
+                    break;
+                }
+                OP_BINARY => return Err(self.fail(CLOSE_UNSUPPORTED_DATA, "binary messages are not supported")),
+                _ => return Err(self.fail(CLOSE_PROTOCOL_ERROR, "unexpected frame")),
+            }
+
+            if message.len() + payload.len() > MAX_MESSAGE_SIZE {
+                return Err(self.fail(CLOSE_TOO_BIG, "message too big"));
+            }
+            message.extend_from_slice(&payload);
+            if fin {
+                return match String::from_utf8(message) {
+                    Ok(text) => Ok(Some(text)),
+                    Err(_) => Err(self.fail(CLOSE_INVALID_DATA, "text message is not valid UTF-8")),
+                };
+            }
+        }
+        Ok(None)
+    }
+
+    /// Send a text message to the client.
+    ///
+    /// # Errors
+    /// Returns `NotConnected` once the connection is closing, or any io error raised while writing.
+    pub fn send(&mut self, text: &str) -> io::Result<()> {
+        if self.closing {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "WebSocket is closing"));
+        }
+        self.write_frame(OP_TEXT, text.as_bytes())
+    }
+
+    /// Start closing the connection, with status 1000 (normal closure).
+    ///
+    /// Keep calling [`WebSocket::recv`] until it returns `Ok(None)` to see the client's
+    /// acknowledgement; messages already on their way are still returned until then.
+    /// Closing again does nothing.
+    ///
+    /// # Errors
+    /// Returns any io error raised while writing.
+    pub fn close(&mut self) -> io::Result<()> {
+        if self.closing {
+            return Ok(());
+        }
+        self.closing = true;
+        self.write_frame(OP_CLOSE, &CLOSE_NORMAL.to_be_bytes())
+    }
+
+    /// Close the connection if the handler returned without doing so. The client may well be
+    /// gone by now, so failing to tell it isn't worth reporting.
+    pub(crate) fn finish(mut self) {
+        let _ = self.close();
+    }
+
+    /// Tell the client the connection is being closed with `code`, and build the error to return.
+    fn fail(&mut self, code: u16, reason: &str) -> io::Error {
+        if !self.closing {
+            self.closing = true;
+            let _ = self.write_frame(OP_CLOSE, &code.to_be_bytes());
+        }
+        io::Error::new(io::ErrorKind::InvalidData, reason)
+    }
+
+    /// Read one frame, returning whether it is the last of its message, its opcode and its
+    /// unmasked payload.
+    fn read_frame(&mut self) -> io::Result<(bool, u8, Vec<u8>)> {
+        let mut head = [0; 2];
+        self.stream.reader().read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        let masked = head[1] & 0x80 != 0;
+
+        // No extensions are negotiated, so the reserved bits must be clear,
+        // and every frame from a client must be masked.
+        if head[0] & 0x70 != 0 || !masked {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "malformed frame"));
+        }
+
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0; 2];
+                self.stream.reader().read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0; 8];
+                self.stream.reader().read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+        // Control frames can't be fragmented and carry at most 125 bytes.
+        if opcode >= OP_CLOSE && (!fin || len > 125) {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "malformed control frame"));
+        }
+        if len > MAX_MESSAGE_SIZE as u64 {
+            return Err(self.fail(CLOSE_TOO_BIG, "message too big"));
+        }
+
+        let mut mask = [0; 4];
+        self.stream.reader().read_exact(&mut mask)?;
+        let mut payload = vec![0; len as usize];
+        self.stream.reader().read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((fin, opcode, payload))
+    }
+
+    /// Write `payload` as a single unmasked frame, as servers must, and flush it.
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+
+        let writer = self.stream.writer();
+        writer.write_all(&frame)?;
+        writer.flush()
+    }
+}
+
+/// Whether `req` asks to switch to the WebSocket protocol.
+pub(crate) fn is_upgrade(req: &Request) -> bool {
+    let upgrade = req.header("Upgrade").unwrap_or("");
+    let connection = req.header("Connection").unwrap_or("");
+    upgrade.split(',').any(|token| token.trim().eq_ignore_ascii_case("websocket"))
+        && connection.split(',').any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+}
+
+/// The answer to a WebSocket upgrade request: `101 Switching Protocols` with the
+/// `Sec-WebSocket-Accept` proving the server understood the handshake.
+///
+/// A request that isn't a `GET` or lacks a valid `Sec-WebSocket-Key` gets 400 Bad Request,
+/// and one for an unsupported protocol version gets `426 Upgrade Required` listing the one
+/// that is.
+pub(crate) fn accept(req: &Request) -> Response {
+    if req.method != Method::Get {
+        return Response::with_status(400);
+    }
+    if req.header("Sec-WebSocket-Version").map(str::trim) != Some(VERSION) {
+        return Response::with_status(426).header("Sec-WebSocket-Version", VERSION);
+    }
+    // The key is 16 random bytes in base64: 24 characters ending in "==".
+    let key = req.header("Sec-WebSocket-Key").map(str::trim).unwrap_or("");
+    if key.len() != 24 || !key.ends_with("==") {
+        return Response::with_status(400);
+    }
+
+    Response::with_status(101)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", &accept_key(key))
+}
+
+/// The `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`:
+/// the base64 SHA-1 digest of the key followed by `HANDSHAKE_GUID`.
+fn accept_key(key: &str) -> String {
+    base64::encode(&sha1(format!("{key}{HANDSHAKE_GUID}").as_bytes()))
+}
+
+/// The SHA-1 digest of `data` (FIPS 180-4). Only fit for the handshake, not for security.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+    // Pad with a 1 bit, zeros, then the length in bits, to a multiple of 64 bytes.
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}